@@ -1,5 +1,8 @@
 use serde::de::DeserializeOwned;
-use std::{env, path::PathBuf};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
 
 pub fn load_config<T: DeserializeOwned>(path: &str) -> Result<T, config::ConfigError> {
     let path = PathBuf::from(path).join("config");
@@ -12,7 +15,7 @@ pub fn load_config<T: DeserializeOwned>(path: &str) -> Result<T, config::ConfigE
     builder.build()?.try_deserialize()
 }
 
-fn join(base: &PathBuf, file: &str) -> String {
+fn join(base: &Path, file: &str) -> String {
     base.join(file)
         .to_str()
         .expect("Path is not valid UTF-8")
@@ -1,35 +1,205 @@
-use super::error::ParseError;
+use super::error::{ErrorKind, ParseError};
+use chrono::{DateTime, Utc};
 use pest::iterators::Pair;
 
 use super::{
-    model::{Expr, Metric, Operator, Query, SymbolMetric, TimeSpec, TimeUnit},
+    model::{
+        Column, Expr, Function, Metric, Operator, OrderBy, Query, SortKey,
+        SortOrder, SymbolMetric, SymbolSet, TimeRange, TimeSpec, TimeUnit,
+    },
     parser::Rule,
 };
 
 type ParseResult<T> = Result<T, ParseError>;
 
 pub(crate) fn build_query(pair: Option<Pair<Rule>>) -> ParseResult<Query> {
-    let pair = pair.ok_or(ParseError::MissingPair("query".into()))?;
+    let pair = pair.ok_or(ParseError::missing_pair("query".into()))?;
     expect_rule(&pair, Rule::query)?;
     let mut pairs = pair.into_inner();
 
     let exprs = build_expr_list(pairs.next())?;
-    let for_clause = build_for_clause(pairs.next())?;
+    let range = build_time_range(pairs.next())?;
     let step_clause = build_step_clause(pairs.next())?;
+    let mut query = Query::new(exprs, range, step_clause);
 
-    Ok(Query::new(exprs, for_clause, step_clause))
+    // optional clauses
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::EOI => {}
+            _ => query = build_clause(pair, query)?,
+        }
+    }
+
+    Ok(query)
 }
 
-pub(crate) fn build_expr_list(pair: Option<Pair<Rule>>) -> ParseResult<Vec<Expr>> {
-    let pair = pair.ok_or(ParseError::MissingPair("expr_list".into()))?;
+/// Builds an optional clause (RESAMPLE, WHERE, ORDER BY, LIMIT or SUMMARY)
+/// and adds it to the query.
+pub(crate) fn build_clause(pair: Pair<Rule>, query: Query) -> ParseResult<Query> {
+    let query = match pair.as_rule() {
+        Rule::resample_clause => {
+            let resample = build_resample_clause(Some(pair), query.step())?;
+            query.with_resample(Some(resample))
+        }
+        Rule::where_clause => {
+            let filter = build_where_clause(Some(pair))?;
+            query.with_filter(Some(filter))
+        }
+        Rule::order_clause => {
+            let order_by = build_order_clause(Some(pair), query.columns())?;
+            query.with_order_by(Some(order_by))
+        }
+        Rule::limit_clause => {
+            let (limit, offset) = build_limit_clause(Some(pair))?;
+            query.with_limit(Some(limit), offset)
+        }
+        Rule::summary_clause => query.with_summary(true),
+        other => {
+            return Err(ParseError::invalid_rule(
+                "summary_clause".into(),
+                other.to_string().into(),
+            )
+            .at(pair.as_span()));
+        }
+    };
+    Ok(query)
+}
+
+pub(crate) fn build_expr_list(pair: Option<Pair<Rule>>) -> ParseResult<Vec<Column>> {
+    let pair = pair.ok_or(ParseError::missing_pair("expr_list".into()))?;
     pair.into_inner()
-        .map(|expr| build_expr(Some(expr)))
+        .map(|column| build_column(Some(column)))
         .collect()
 }
 
-pub(crate) fn build_expr(pair: Option<Pair<Rule>>) -> Result<Expr, ParseError> {
-    let pair = pair.ok_or(ParseError::MissingPair("expr".into()))?;
+/// Builds a column of the GET list. A column may have symbol sets (`{AAPL, MSFT}.close`),
+/// expanded into a column per symbol before the query runs; they must all be the same.
+pub(crate) fn build_column(pair: Option<Pair<Rule>>) -> ParseResult<Column> {
+    let pair = pair.ok_or(ParseError::missing_pair("column".into()))?;
+    expect_rule(&pair, Rule::column)?;
+    check_symbol_sets(&pair)?;
+
+    let mut inner = pair.into_inner();
+    let expr = build_expr(inner.next())?;
+    let alias = inner.next().map(|p| build_alias(Some(p))).transpose()?;
+
+    Ok(Column::new(expr, alias))
+}
+
+/// Fails if the symbol sets listed in a column are not all the same.
+fn check_symbol_sets(pair: &Pair<Rule>) -> ParseResult<()> {
+    let mut first: Option<(Pair<Rule>, SymbolSet)> = None;
+    for set in pair.clone().into_inner().flatten() {
+        if set.as_rule() != Rule::symbol_set {
+            continue;
+        }
+        let symbols = build_symbol_set(set.clone())?;
+        match &first {
+            Some((first, first_symbols)) if *first_symbols != symbols => {
+                return Err(ParseError::new(ErrorKind::SymbolSetMismatch(
+                    first.as_str().to_string().into(),
+                    set.as_str().to_string().into(),
+                ))
+                .at(set.as_span()));
+            }
+            Some(_) => {}
+            None => first = Some((set, symbols)),
+        }
+    }
+    Ok(())
+}
+
+/// Builds the symbols listed in a set, qualified by their exchange (`XNAS:AAPL`).
+fn build_symbol_set(pair: Pair<Rule>) -> ParseResult<SymbolSet> {
+    let symbols = pair
+        .into_inner()
+        .map(|symbol| {
+            let (exchange, symbol) = build_symbol(Some(symbol))?;
+            Ok(match exchange {
+                Some(exchange) => format!("{}:{}", exchange, symbol),
+                None => symbol,
+            })
+        })
+        .collect::<ParseResult<_>>()?;
+    Ok(SymbolSet::List(symbols))
+}
+
+/// Fails if the expression (or condition) has a symbol set or a universe,
+/// which only a column may have.
+pub(crate) fn reject_symbol_sets(pair: Option<Pair<Rule>>) -> ParseResult<Option<Pair<Rule>>> {
+    let set = pair
+        .iter()
+        .flat_map(|pair| pair.clone().into_inner().flatten())
+        .find(|p| matches!(p.as_rule(), Rule::symbol_set | Rule::universe));
+    let Some(set) = set else {
+        return Ok(pair);
+    };
+    let kind = match set.as_rule() {
+        Rule::universe => ErrorKind::MisplacedUniverse(set.as_str().to_string().into()),
+        _ => ErrorKind::MisplacedSymbolSet(set.as_str().to_string().into()),
+    };
+    Err(ParseError::new(kind).at(set.as_span()))
+}
+
+pub(crate) fn build_alias(pair: Option<Pair<Rule>>) -> ParseResult<String> {
+    let val = pair.ok_or(ParseError::missing_pair("alias".into()))?;
+    expect_rule(&val, Rule::alias)?;
+    Ok(val.as_str().to_string())
+}
+
+pub(crate) fn build_expr(pair: Option<Pair<Rule>>) -> ParseResult<Expr> {
+    let pair = pair.ok_or(ParseError::missing_pair("expr".into()))?;
     expect_rule(&pair, Rule::expr)?;
+    build_binary_chain(pair, build_and_expr)
+}
+
+pub(crate) fn build_and_expr(pair: Option<Pair<Rule>>) -> ParseResult<Expr> {
+    let pair = pair.ok_or(ParseError::missing_pair("and_expr".into()))?;
+    expect_rule(&pair, Rule::and_expr)?;
+    build_binary_chain(pair, build_not_expr)
+}
+
+pub(crate) fn build_not_expr(pair: Option<Pair<Rule>>) -> ParseResult<Expr> {
+    let pair = pair.ok_or(ParseError::missing_pair("not_expr".into()))?;
+    expect_rule(&pair, Rule::not_expr)?;
+
+    let mut inner = pair.into_inner();
+    let pair = inner
+        .next()
+        .ok_or(ParseError::missing_pair("not_expr".into()))?;
+    match pair.as_rule() {
+        Rule::not_op => Ok(Expr::Not(Box::new(build_not_expr(inner.next())?))),
+        _ => build_cmp_expr(Some(pair)),
+    }
+}
+
+pub(crate) fn build_cmp_expr(pair: Option<Pair<Rule>>) -> ParseResult<Expr> {
+    let pair = pair.ok_or(ParseError::missing_pair("cmp_expr".into()))?;
+    expect_rule(&pair, Rule::cmp_expr)?;
+    build_binary_chain(pair, build_sum)
+}
+
+/// Builds a left-associative chain of binary operations, i.e. `a OR b OR c`,
+/// from operands alternating with operators.
+fn build_binary_chain(
+    pair: Pair<Rule>,
+    build_operand: fn(Option<Pair<Rule>>) -> ParseResult<Expr>,
+) -> ParseResult<Expr> {
+    let mut inner = pair.into_inner();
+    let mut left = build_operand(inner.next())?;
+
+    while let Some(op_pair) = inner.next() {
+        let op = Operator::try_from(op_pair.as_str()).map_err(|e| e.at(op_pair.as_span()))?;
+        let right = build_operand(inner.next())?;
+        left = Expr::Binary(Box::new(left), op, Box::new(right));
+    }
+
+    Ok(left)
+}
+
+pub(crate) fn build_sum(pair: Option<Pair<Rule>>) -> ParseResult<Expr> {
+    let pair = pair.ok_or(ParseError::missing_pair("sum".into()))?;
+    expect_rule(&pair, Rule::sum)?;
 
     let mut inner = pair.into_inner();
     let mut left = build_term(inner.next())?;
@@ -38,14 +208,15 @@ pub(crate) fn build_expr(pair: Option<Pair<Rule>>) -> Result<Expr, ParseError> {
         match op_pair.as_rule() {
             Rule::expr_op => {
                 let right = build_term(inner.next())?;
-                let op = Operator::try_from(op_pair.as_str())?;
+                let op =
+                    Operator::try_from(op_pair.as_str()).map_err(|e| e.at(op_pair.as_span()))?;
                 left = Expr::Binary(Box::new(left), op, Box::new(right));
             }
             other => {
-                return Err(ParseError::InvalidRule(
-                    "expr".into(),
-                    other.to_string().into(),
-                ));
+                return Err(
+                    ParseError::invalid_rule("expr_op".into(), other.to_string().into())
+                        .at(op_pair.as_span()),
+                );
             }
         }
     }
@@ -54,7 +225,7 @@ pub(crate) fn build_expr(pair: Option<Pair<Rule>>) -> Result<Expr, ParseError> {
 }
 
 pub(crate) fn build_term(pair: Option<Pair<Rule>>) -> ParseResult<Expr> {
-    let pair = pair.ok_or(ParseError::MissingPair("term".into()))?;
+    let pair = pair.ok_or(ParseError::missing_pair("term".into()))?;
 
     let mut inner = pair.into_inner();
     let next_pair = inner.next();
@@ -63,16 +234,17 @@ pub(crate) fn build_term(pair: Option<Pair<Rule>>) -> ParseResult<Expr> {
     while let Some(next_pair) = inner.next() {
         match next_pair.as_rule() {
             Rule::term_op => {
-                let op = Operator::try_from(next_pair.as_str())?;
+                let op = Operator::try_from(next_pair.as_str())
+                    .map_err(|e| e.at(next_pair.as_span()))?;
                 let next_pair = inner.next();
                 let right = build_factor(next_pair)?;
                 left = Expr::Binary(Box::new(left), op, Box::new(right));
             }
             other => {
-                return Err(ParseError::InvalidRule(
-                    "term_op".into(),
-                    other.to_string().into(),
-                ));
+                return Err(
+                    ParseError::invalid_rule("term_op".into(), other.to_string().into())
+                        .at(next_pair.as_span()),
+                );
             }
         }
     }
@@ -81,35 +253,211 @@ pub(crate) fn build_term(pair: Option<Pair<Rule>>) -> ParseResult<Expr> {
 }
 
 pub(crate) fn build_factor(pair: Option<Pair<Rule>>) -> ParseResult<Expr> {
-    let pair = pair.ok_or(ParseError::MissingPair("factor".into()))?;
+    let pair = pair.ok_or(ParseError::missing_pair("factor".into()))?;
     expect_rule(&pair, Rule::factor)?;
 
     let mut inner = pair.into_inner();
     let pair = inner
         .next()
-        .ok_or(ParseError::MissingPair("factor".into()))?;
+        .ok_or(ParseError::missing_pair("factor".into()))?;
 
     let val = match pair.as_rule() {
         Rule::data => {
+            let span = Some(pair.as_span().start()..pair.as_span().end());
             let mut inner = pair.into_inner();
-            let symbol = build_symbol(inner.next())?;
+            let symbol = inner.next();
             let metric = build_metric(inner.next())?;
-            Expr::Data(SymbolMetric::new(&symbol, metric))
+            let symbol = match symbol {
+                Some(universe) if universe.as_rule() == Rule::universe => {
+                    let name = universe.into_inner().as_str();
+                    let data = SymbolMetric::universe(name, metric).with_span(span);
+                    return Ok(Expr::Data(data));
+                }
+                Some(set) if set.as_rule() == Rule::symbol_set => {
+                    let data = SymbolMetric::of_set(build_symbol_set(set)?, metric);
+                    return Ok(Expr::Data(data.with_span(span)));
+                }
+                symbol => symbol,
+            };
+            let (exchange, symbol) = build_symbol(symbol)?;
+            let data = SymbolMetric::new(&symbol, metric).with_exchange(exchange.as_deref());
+            Expr::Data(data.with_span(span))
         }
-        Rule::value => Expr::Value(build_value(Some(pair))?),
+        Rule::number => Expr::Value(build_number(Some(pair))?),
+        Rule::neg => build_neg(Some(pair))?,
+        Rule::call => build_call(Some(pair))?,
+        Rule::case_expr => build_case(Some(pair))?,
         Rule::expr => build_expr(Some(pair))?, // for grouped expressions: (a + b)
         other => {
-            return Err(ParseError::InvalidRule(
-                "data, value or expr".into(),
+            return Err(ParseError::invalid_rule(
+                "data, number, neg, call, case_expr or expr".into(),
                 other.to_string().into(),
-            ));
+            )
+            .at(pair.as_span()));
+        }
+    };
+    Ok(val)
+}
+
+pub(crate) fn build_case(pair: Option<Pair<Rule>>) -> ParseResult<Expr> {
+    let pair = pair.ok_or(ParseError::missing_pair("case_expr".into()))?;
+    expect_rule(&pair, Rule::case_expr)?;
+
+    let mut branches = Vec::new();
+    let mut default = None;
+    for pair in pair.into_inner() {
+        match pair.as_rule() {
+            Rule::case_when => {
+                let mut inner = pair.into_inner();
+                let cond = build_expr(inner.next())?;
+                let value = build_expr(inner.next())?;
+                branches.push((cond, value));
+            }
+            Rule::expr => default = Some(Box::new(build_expr(Some(pair))?)),
+            other => {
+                return Err(ParseError::invalid_rule(
+                    "case_when or expr".into(),
+                    other.to_string().into(),
+                )
+                .at(pair.as_span()));
+            }
         }
+    }
+
+    Ok(Expr::Case(branches, default))
+}
+
+pub(crate) fn build_neg(pair: Option<Pair<Rule>>) -> ParseResult<Expr> {
+    let pair = pair.ok_or(ParseError::missing_pair("neg".into()))?;
+    expect_rule(&pair, Rule::neg)?;
+
+    // negative literals are folded into a single value: -3 => Value(-3)
+    let val = match build_factor(pair.into_inner().next())? {
+        Expr::Value(val) => Expr::Value(-val),
+        other => Expr::Neg(Box::new(other)),
     };
     Ok(val)
 }
 
+pub(crate) fn build_call(pair: Option<Pair<Rule>>) -> ParseResult<Expr> {
+    let pair = pair.ok_or(ParseError::missing_pair("call".into()))?;
+    expect_rule(&pair, Rule::call)?;
+    let span = pair.as_span();
+
+    let mut inner = pair.into_inner();
+    let name = inner
+        .next()
+        .ok_or(ParseError::missing_pair("ident".into()))?;
+    expect_rule(&name, Rule::ident)?;
+
+    let arg_pairs: Vec<Pair<Rule>> = inner.collect();
+    let last_arg_span = arg_pairs.last().map_or(span, |arg| arg.as_span());
+    let args = arg_pairs
+        .into_iter()
+        .map(|arg| build_expr(Some(arg)))
+        .collect::<ParseResult<Vec<Expr>>>()?;
+
+    // IF(cond, a, b) is a conditional rather than a function
+    if name.as_str().eq_ignore_ascii_case("if") {
+        let [cond, then, other] = <[Expr; 3]>::try_from(args)
+            .map_err(|args| ParseError::invalid_arity("IF".into(), 3, args.len()).at(span))?;
+        return Ok(Expr::Case(vec![(cond, then)], Some(Box::new(other))));
+    }
+    let func = Function::try_from(name.as_str()).map_err(|e| e.at(name.as_span()))?;
+
+    let arity = func.arity();
+    if !arity.contains(&args.len()) {
+        let expected = if args.len() < *arity.start() {
+            *arity.start()
+        } else {
+            *arity.end()
+        };
+        return Err(
+            ParseError::invalid_arity(func.to_string().into(), expected, args.len()).at(span),
+        );
+    }
+    if func.takes_period() && func.period(&args).is_none() {
+        let arg = args.last().map(|a| a.to_string()).unwrap_or_default();
+        return Err(ParseError::invalid_value(arg.into(), "period".into()).at(last_arg_span));
+    }
+    if func == Function::Percentile
+        && !matches!(args.last(), Some(Expr::Value(p)) if (0.0..=100.0).contains(p))
+    {
+        let arg = args.last().map(|a| a.to_string()).unwrap_or_default();
+        return Err(ParseError::invalid_value(arg.into(), "percentile".into()).at(last_arg_span));
+    }
+    Ok(Expr::Call(func, args))
+}
+
+pub(crate) fn build_where_clause(pair: Option<Pair<Rule>>) -> ParseResult<Expr> {
+    let pair = pair.ok_or(ParseError::missing_pair("where_clause".into()))?;
+    expect_rule(&pair, Rule::where_clause)?;
+    build_condition(reject_symbol_sets(pair.into_inner().next())?)
+}
+
+/// Builds a condition: an expression yielding a boolean, i.e. comparisons combined
+/// with AND, OR and NOT.
+pub(crate) fn build_condition(pair: Option<Pair<Rule>>) -> ParseResult<Expr> {
+    let pair = pair.ok_or(ParseError::missing_pair("condition".into()))?;
+    let span = pair.as_span();
+    let expr = build_expr(Some(pair))?;
+    match expr.is_boolean() {
+        true => Ok(expr),
+        false => Err(ParseError::invalid_value(expr.to_string().into(), "condition".into()).at(span)),
+    }
+}
+
+/// Builds the ORDER BY clause. A bare name must be an alias of one of the `columns`.
+pub(crate) fn build_order_clause(
+    pair: Option<Pair<Rule>>,
+    columns: &[Column],
+) -> ParseResult<OrderBy> {
+    let pair = pair.ok_or(ParseError::missing_pair("order_clause".into()))?;
+    expect_rule(&pair, Rule::order_clause)?;
+
+    let mut inner = pair.into_inner();
+    let key = inner
+        .next()
+        .and_then(|key| key.into_inner().next())
+        .ok_or(ParseError::missing_pair("sort_key".into()))?;
+    let span = key.as_span();
+    let key = match key.as_rule() {
+        Rule::expr => SortKey::Expr(build_expr(reject_symbol_sets(Some(key))?)?),
+        Rule::alias => {
+            let alias = build_alias(Some(key))?;
+            if !columns.iter().any(|c| c.alias() == Some(alias.as_str())) {
+                return Err(ParseError::invalid_value(alias.into(), "sort_key".into()).at(span));
+            }
+            SortKey::Alias(alias)
+        }
+        other => {
+            return Err(
+                ParseError::invalid_rule("sort_key".into(), other.to_string().into()).at(span),
+            );
+        }
+    };
+    let order = match inner.next() {
+        Some(order) if order.as_str().eq_ignore_ascii_case("DESC") => SortOrder::Desc,
+        _ => SortOrder::Asc,
+    };
+
+    Ok(OrderBy::new(key, order))
+}
+
+/// Builds the LIMIT clause into the number of rows and the offset (0 if not given).
+pub(crate) fn build_limit_clause(pair: Option<Pair<Rule>>) -> ParseResult<(usize, usize)> {
+    let pair = pair.ok_or(ParseError::missing_pair("limit_clause".into()))?;
+    expect_rule(&pair, Rule::limit_clause)?;
+
+    let mut inner = pair.into_inner();
+    let limit = build_value(inner.next())?;
+    let offset = inner.next().map(|p| build_value(Some(p))).transpose()?;
+
+    Ok((limit as usize, offset.unwrap_or(0) as usize))
+}
+
 pub(crate) fn build_step_clause(pair: Option<Pair<Rule>>) -> ParseResult<TimeSpec> {
-    let pair = pair.ok_or(ParseError::MissingPair("step_clause".into()))?;
+    let pair = pair.ok_or(ParseError::missing_pair("step_clause".into()))?;
     expect_rule(&pair, Rule::step_clause)?;
 
     let mut inner = pair.into_inner();
@@ -119,8 +467,46 @@ pub(crate) fn build_step_clause(pair: Option<Pair<Rule>>) -> ParseResult<TimeSpe
     Ok(TimeSpec::new(value, unit))
 }
 
+/// Builds the size of bars fetched for resampling. If not given explicitly,
+/// it is one unit finer than the step, i.e. `STEP 1 day RESAMPLE` fetches hourly bars.
+/// The step must be a whole multiple of the bar.
+pub(crate) fn build_resample_clause(
+    pair: Option<Pair<Rule>>,
+    step: &TimeSpec,
+) -> ParseResult<TimeSpec> {
+    let pair = pair.ok_or(ParseError::missing_pair("resample_clause".into()))?;
+    expect_rule(&pair, Rule::resample_clause)?;
+    let span = pair.as_span();
+
+    let mut inner = pair.into_inner();
+    let bar = match inner.next() {
+        Some(value) => TimeSpec::new(build_value(Some(value))?, build_time_unit(inner.next())?),
+        None => TimeSpec::new(1, finer_unit(step.unit())),
+    };
+
+    let (step_secs, bar_secs) = (step.to_seconds(), bar.to_seconds());
+    if bar_secs == 0 || bar_secs >= step_secs || step_secs % bar_secs != 0 {
+        return Err(ParseError::invalid_value(
+            span.as_str().to_string().into(),
+            "resample_clause".into(),
+        )
+        .at(span));
+    }
+    Ok(bar)
+}
+
+fn finer_unit(unit: TimeUnit) -> TimeUnit {
+    use TimeUnit::*;
+    match unit {
+        Month | Week => Day,
+        Day => Hour,
+        Hour => Minute,
+        Minute | Second => Second,
+    }
+}
+
 pub(crate) fn build_for_clause(pair: Option<Pair<Rule>>) -> ParseResult<TimeSpec> {
-    let pair = pair.ok_or(ParseError::MissingPair("for_clause".into()))?;
+    let pair = pair.ok_or(ParseError::missing_pair("for_clause".into()))?;
     expect_rule(&pair, Rule::for_clause)?;
 
     let mut inner = pair.into_inner();
@@ -130,41 +516,124 @@ pub(crate) fn build_for_clause(pair: Option<Pair<Rule>>) -> ParseResult<TimeSpec
     Ok(TimeSpec::new(value, unit))
 }
 
-pub(crate) fn build_symbol(pair: Option<Pair<Rule>>) -> ParseResult<String> {
-    let val = pair.ok_or(ParseError::MissingPair("symbol".into()))?;
+pub(crate) fn build_time_range(pair: Option<Pair<Rule>>) -> ParseResult<TimeRange> {
+    let pair = pair.ok_or(ParseError::missing_pair("time_range".into()))?;
+    expect_rule(&pair, Rule::time_range)?;
+
+    let mut inner = pair.into_inner();
+    let pair = inner
+        .next()
+        .ok_or(ParseError::missing_pair("time_range".into()))?;
+
+    let range = match pair.as_rule() {
+        Rule::for_clause => TimeRange::Last(build_for_clause(Some(pair))?),
+        Rule::from_clause => build_from_clause(Some(pair))?,
+        other => {
+            return Err(ParseError::invalid_rule(
+                "for_clause or from_clause".into(),
+                other.to_string().into(),
+            )
+            .at(pair.as_span()));
+        }
+    };
+    Ok(range)
+}
+
+pub(crate) fn build_from_clause(pair: Option<Pair<Rule>>) -> ParseResult<TimeRange> {
+    let pair = pair.ok_or(ParseError::missing_pair("from_clause".into()))?;
+    expect_rule(&pair, Rule::from_clause)?;
+
+    let mut inner = pair.into_inner();
+    let from = build_timestamp(inner.next())?;
+    let to_pair = inner.next();
+    let to_span = to_pair.as_ref().map(|p| p.as_span());
+    let to = build_timestamp(to_pair)?;
+
+    if let Some(span) = to_span.filter(|_| to <= from) {
+        return Err(ParseError::invalid_value(
+            span.as_str().to_string().into(),
+            "from_clause".into(),
+        )
+        .at(span));
+    }
+    Ok(TimeRange::Between(from, to))
+}
+
+pub(crate) fn build_timestamp(pair: Option<Pair<Rule>>) -> ParseResult<DateTime<Utc>> {
+    let pair = pair.ok_or(ParseError::missing_pair("timestamp".into()))?;
+    expect_rule(&pair, Rule::timestamp)?;
+
+    let val = pair
+        .into_inner()
+        .next()
+        .ok_or(ParseError::missing_pair("timestamp_value".into()))?;
+    let valstr = val.as_str();
+
+    DateTime::parse_from_rfc3339(valstr)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|_| {
+            ParseError::invalid_value(valstr.to_string().into(), "timestamp".into())
+                .at(val.as_span())
+        })
+}
+
+/// Builds the symbol (unquoted) and the exchange qualifying it, if any.
+pub(crate) fn build_symbol(pair: Option<Pair<Rule>>) -> ParseResult<(Option<String>, String)> {
+    let val = pair.ok_or(ParseError::missing_pair("symbol".into()))?;
     expect_rule(&val, Rule::symbol)?;
-    Ok(val.as_str().to_string())
+
+    let mut exchange = None;
+    for part in val.into_inner() {
+        match part.as_rule() {
+            Rule::exchange => exchange = Some(part.as_str().to_string()),
+            _ => return Ok((exchange, part.as_str().to_string())),
+        }
+    }
+    Err(ParseError::missing_pair("symbol".into()))
 }
 
 pub(crate) fn build_metric(pair: Option<Pair<Rule>>) -> ParseResult<Metric> {
-    let val = pair.ok_or(ParseError::MissingPair("metric".into()))?;
+    let val = pair.ok_or(ParseError::missing_pair("metric".into()))?;
     expect_rule(&val, Rule::metric)?;
-    Metric::try_from(val.as_str())
+    Ok(Metric::new(val.as_str().to_string()))
 }
 
 pub(crate) fn build_value(pair: Option<Pair<Rule>>) -> ParseResult<u32> {
-    let val = pair.ok_or(ParseError::MissingPair("value".into()))?;
+    let val = pair.ok_or(ParseError::missing_pair("value".into()))?;
     expect_rule(&val, Rule::value)?;
     let valstr = val.as_str().to_string();
 
     valstr
         .parse()
-        .map_err(|_| ParseError::InvalidValue(valstr.into(), "value".into()))
+        .map_err(|_| ParseError::invalid_value(valstr.into(), "value".into()).at(val.as_span()))
+}
+
+pub(crate) fn build_number(pair: Option<Pair<Rule>>) -> ParseResult<f64> {
+    let val = pair.ok_or(ParseError::missing_pair("number".into()))?;
+    expect_rule(&val, Rule::number)?;
+    let valstr = val.as_str().to_string();
+
+    // a literal too large for f64 (`1e400`) would be infinite, which has no literal to print back
+    valstr
+        .parse()
+        .ok()
+        .filter(|number: &f64| number.is_finite())
+        .ok_or_else(|| ParseError::invalid_value(valstr.into(), "number".into()).at(val.as_span()))
 }
 
 pub(crate) fn build_time_unit(pair: Option<Pair<Rule>>) -> ParseResult<TimeUnit> {
-    let val = pair.ok_or(ParseError::MissingPair("timeunit".into()))?;
+    let val = pair.ok_or(ParseError::missing_pair("timeunit".into()))?;
     expect_rule(&val, Rule::time_unit)?;
-    TimeUnit::try_from(val.as_str())
+    TimeUnit::try_from(val.as_str()).map_err(|e| e.at(val.as_span()))
 }
 
 fn expect_rule(pair: &Pair<Rule>, expected: Rule) -> ParseResult<()> {
     let rule = pair.as_rule();
     if rule != expected {
-        return Err(ParseError::InvalidRule(
-            expected.to_string().into(),
-            rule.to_string().into(),
-        ));
+        return Err(
+            ParseError::invalid_rule(expected.to_string().into(), rule.to_string().into())
+                .at(pair.as_span()),
+        );
     }
     Ok(())
 }
@@ -0,0 +1,136 @@
+use std::{borrow::Cow, collections::BTreeMap, sync::LazyLock};
+
+use super::{
+    model::{Metric, Rollup},
+    suggest::suggest,
+};
+
+/// What is known of a metric: the unit of its values and how they are combined
+/// when bars are resampled.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MetricInfo {
+    unit: Cow<'static, str>,
+    rollup: Rollup,
+}
+
+impl MetricInfo {
+    pub fn new(unit: impl Into<Cow<'static, str>>, rollup: Rollup) -> Self {
+        Self {
+            unit: unit.into(),
+            rollup,
+        }
+    }
+
+    pub fn unit(&self) -> &str {
+        &self.unit
+    }
+
+    pub fn rollup(&self) -> Rollup {
+        self.rollup
+    }
+}
+
+static WELL_KNOWN: LazyLock<MetricCatalog> = LazyLock::new(|| {
+    let price = |rollup| MetricInfo::new("currency", rollup);
+    MetricCatalog::empty()
+        .with(Metric::OPEN, price(Rollup::First))
+        .with(Metric::CLOSE, price(Rollup::Last))
+        .with(Metric::MAX, price(Rollup::Max))
+        .with(Metric::MIN, price(Rollup::Min))
+        .with(Metric::AVG, price(Rollup::VolumeWeighted))
+        .with(Metric::VOLUME, MetricInfo::new("shares", Rollup::Sum))
+});
+
+/// The metrics queries may refer to. The parser accepts any metric name, the catalog
+/// tells which exist (see [`validate`](crate::validate)) and how to resample them.
+/// The default catalog holds the well-known metrics; more can be added, i.e. from
+/// configuration or as discovered from the data source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricCatalog {
+    metrics: BTreeMap<Metric, MetricInfo>,
+}
+
+impl MetricCatalog {
+    /// Catalog without any metric.
+    pub fn empty() -> Self {
+        Self {
+            metrics: BTreeMap::new(),
+        }
+    }
+
+    /// Catalog of the well-known metrics: prices (open, close, max, min, avg) and volume.
+    pub fn well_known() -> &'static MetricCatalog {
+        &WELL_KNOWN
+    }
+
+    pub fn with(mut self, metric: Metric, info: MetricInfo) -> Self {
+        self.insert(metric, info);
+        self
+    }
+
+    /// Adds a metric, or replaces what is known of it. Returns the replaced info.
+    pub fn insert(&mut self, metric: Metric, info: MetricInfo) -> Option<MetricInfo> {
+        self.metrics.insert(metric, info)
+    }
+
+    pub fn get(&self, metric: &Metric) -> Option<&MetricInfo> {
+        self.metrics.get(metric)
+    }
+
+    pub fn contains(&self, metric: &Metric) -> bool {
+        self.metrics.contains_key(metric)
+    }
+
+    /// Metrics of the catalog, sorted by name.
+    pub fn metrics(&self) -> impl Iterator<Item = (&Metric, &MetricInfo)> {
+        self.metrics.iter()
+    }
+
+    /// Metrics of the catalog a misspelled name is likely meant to be.
+    pub fn suggest(&self, name: &str) -> Vec<String> {
+        let names: Vec<&str> = self.metrics.keys().map(Metric::name).collect();
+        suggest(name, &names)
+    }
+}
+
+impl Default for MetricCatalog {
+    fn default() -> Self {
+        Self::well_known().clone()
+    }
+}
+
+impl Extend<(Metric, MetricInfo)> for MetricCatalog {
+    fn extend<T: IntoIterator<Item = (Metric, MetricInfo)>>(&mut self, iter: T) {
+        self.metrics.extend(iter);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_catalog() {
+        let mut catalog = MetricCatalog::default();
+        assert_eq!(
+            Some(Rollup::Last),
+            catalog.get(&Metric::CLOSE).map(MetricInfo::rollup)
+        );
+        assert_eq!(
+            Some("shares"),
+            catalog.get(&Metric::new("volume")).map(MetricInfo::unit)
+        );
+        assert!(!catalog.contains(&Metric::new("vwap")));
+
+        catalog.extend([(
+            Metric::new("vwap".to_string()),
+            MetricInfo::new("currency", Rollup::VolumeWeighted),
+        )]);
+        assert!(catalog.contains(&Metric::new("vwap")));
+        assert_eq!(7, catalog.metrics().count());
+        assert_eq!(vec!["vwap"], catalog.suggest("vwao"));
+        assert_eq!(vec!["close"], catalog.suggest("Close"));
+        assert!(MetricCatalog::empty().suggest("close").is_empty());
+    }
+}
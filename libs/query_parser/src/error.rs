@@ -1,22 +1,385 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, fmt, ops::Range};
 
-use thiserror;
+use pest::error::{ErrorVariant, InputLocation};
 
-use super::parser::Rule;
+use super::{parser::Rule, suggest::suggest_any};
 
-#[derive(thiserror::Error, Debug)]
-pub enum ParseError {
+/// Error of parsing a query. Besides the kind of the problem it carries, where known,
+/// the position in the source: a byte span, line and column, the offending token
+/// and the tokens expected instead. Misspelled keywords and units come with
+/// suggestions of what was likely meant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError(Box<ErrorDetails>);
+
+#[derive(Debug, Clone, PartialEq)]
+struct ErrorDetails {
+    kind: ErrorKind,
+    span: Option<Range<usize>>,
+    line_col: Option<(usize, usize)>,
+    token: Option<String>,
+    expected: Vec<String>,
+    snippet: Option<String>,
+    suggestions: Vec<String>,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ErrorKind {
     #[error("Missing pair: {0}")]
     MissingPair(Cow<'static, str>),
 
     #[error("Invalid rule. Expected {0}, but found {1}")]
     InvalidRule(Cow<'static, str>, Cow<'static, str>),
 
-    #[error("Invalid value: {0} for {1} rule ")]
+    #[error("Invalid value: {0} for {1} rule")]
     InvalidValue(Cow<'static, str>, Cow<'static, str>),
 
-    #[error("Internal parser error: {0}")]
-    Internal(#[from] pest::error::Error<Rule>),
+    #[error("Unknown function: {0}")]
+    UnknownFunction(Cow<'static, str>),
+
+    #[error("Function {0} expects {1} argument(s), but got {2}")]
+    InvalidArity(Cow<'static, str>, usize, usize),
+
+    #[error("Unknown {0} '{1}'")]
+    Unknown(Cow<'static, str>, Cow<'static, str>),
+
+    #[error("Symbol set {0} is only allowed in GET columns")]
+    MisplacedSymbolSet(Cow<'static, str>),
+
+    #[error("Symbol sets of a column must be the same, but found {0} and {1}")]
+    SymbolSetMismatch(Cow<'static, str>, Cow<'static, str>),
+
+    #[error("Universe {0} is only allowed in GET columns")]
+    MisplacedUniverse(Cow<'static, str>),
+
+    #[error("Syntax error")]
+    Syntax,
+}
+
+impl ParseError {
+    pub fn new(kind: ErrorKind) -> Self {
+        Self(Box::new(ErrorDetails {
+            kind,
+            span: None,
+            line_col: None,
+            token: None,
+            expected: Vec::new(),
+            snippet: None,
+            suggestions: Vec::new(),
+        }))
+    }
+
+    pub fn missing_pair(rule: Cow<'static, str>) -> Self {
+        Self::new(ErrorKind::MissingPair(rule))
+    }
+
+    pub fn invalid_rule(expected: Cow<'static, str>, found: Cow<'static, str>) -> Self {
+        Self::new(ErrorKind::InvalidRule(expected, found))
+    }
+
+    pub fn invalid_value(value: Cow<'static, str>, rule: Cow<'static, str>) -> Self {
+        Self::new(ErrorKind::InvalidValue(value, rule))
+    }
+
+    pub fn unknown_function(name: Cow<'static, str>) -> Self {
+        Self::new(ErrorKind::UnknownFunction(name))
+    }
+
+    pub fn invalid_arity(name: Cow<'static, str>, expected: usize, found: usize) -> Self {
+        Self::new(ErrorKind::InvalidArity(name, expected, found))
+    }
+
+    /// Error for a word that is not a known `what` (i.e. "metric"), with the known words
+    /// it is close to.
+    pub fn unknown(
+        what: Cow<'static, str>,
+        word: Cow<'static, str>,
+        suggestions: Vec<String>,
+    ) -> Self {
+        let mut error = Self::new(ErrorKind::Unknown(what, word));
+        error.0.suggestions = suggestions;
+        error
+    }
+
+    /// Sets the byte range of the error, unless it is already known.
+    pub(crate) fn within(mut self, span: Option<Range<usize>>) -> Self {
+        if self.0.span.is_none() {
+            self.0.span = span;
+        }
+        self
+    }
+
+    /// Syntax error at byte position `pos`, where one of `expected` should have been.
+    pub(crate) fn unexpected(pos: usize, expected: Vec<String>) -> Self {
+        let mut error = Self::new(ErrorKind::Syntax);
+        error.0.span = Some(pos..pos);
+        error.0.expected = expected;
+        error
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.0.kind
+    }
+
+    /// Byte range of the offending part of the source.
+    pub fn span(&self) -> Option<Range<usize>> {
+        self.0.span.clone()
+    }
+
+    /// Line and column (both starting at 1) where the offending part begins.
+    pub fn line_col(&self) -> Option<(usize, usize)> {
+        self.0.line_col
+    }
+
+    /// The offending part of the source, or "end of input".
+    pub fn token(&self) -> Option<&str> {
+        self.0.token.as_deref()
+    }
+
+    /// Tokens (or grammar rules) that would be accepted at the position.
+    pub fn expected(&self) -> &[String] {
+        &self.0.expected
+    }
+
+    /// Source line with the offending part underlined by carets.
+    pub fn snippet(&self) -> Option<&str> {
+        self.0.snippet.as_deref()
+    }
+
+    /// Known words the offending one is likely a misspelling of.
+    pub fn suggestions(&self) -> &[String] {
+        &self.0.suggestions
+    }
+
+    /// Sets the span of the error, unless it is already known
+    /// (errors get the span of the innermost element they are raised for).
+    pub(crate) fn at(mut self, span: pest::Span) -> Self {
+        if self.0.span.is_none() {
+            self.0.span = Some(span.start()..span.end());
+        }
+        self
+    }
+
+    /// Moves the span by `offset` bytes, for errors raised parsing a part of the source
+    /// which starts at `offset`.
+    pub(crate) fn shifted(mut self, offset: usize) -> Self {
+        if let Some(span) = &mut self.0.span {
+            *span = span.start + offset..span.end + offset;
+        }
+        self
+    }
+
+    /// Resolves the span against the parsed source: computes line and column,
+    /// the offending token and the snippet.
+    pub(crate) fn with_source(mut self, src: &str) -> Self {
+        let Some(span) = self.0.span.clone() else {
+            return self;
+        };
+        let mut start = span.start.min(src.len());
+        let mut end = span.end.clamp(start, src.len());
+
+        // a syntax error within or at a misspelled word is reported for the whole word
+        if self.0.kind == ErrorKind::Syntax {
+            let (word_start, word_end) = word_around(src, start);
+            let word = &src[word_start..word_end];
+            if let Some((what, suggestions)) = suggest_any(word, &self.0.expected) {
+                self.0.kind = ErrorKind::Unknown(what.into(), word.to_string().into());
+                self.0.suggestions = suggestions;
+                (start, end) = (word_start, word_end);
+            }
+        }
+
+        // an empty span (position) points at the following word
+        if end == start {
+            end = src[start..]
+                .find(char::is_whitespace)
+                .map_or(src.len(), |len| start + len);
+        }
+        self.0.token = Some(match &src[start..end] {
+            "" => "end of input".to_string(),
+            token => token.to_string(),
+        });
+
+        let line_start = src[..start].rfind('\n').map_or(0, |pos| pos + 1);
+        let line_end = src[start..].find('\n').map_or(src.len(), |pos| start + pos);
+        let line = src[..start].matches('\n').count() + 1;
+        let column = src[line_start..start].chars().count() + 1;
+        let width = src[start..end.min(line_end)].chars().count().max(1);
+
+        self.0.span = Some(start..end);
+        self.0.line_col = Some((line, column));
+        self.0.snippet = Some(render_snippet(
+            src[line_start..line_end].trim_end_matches('\r'),
+            line,
+            column,
+            width,
+        ));
+        self
+    }
 }
 
+/// Bounds of the word (letters, digits and underscores) containing or following `pos`.
+fn word_around(src: &str, pos: usize) -> (usize, usize) {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let start = src[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_word(*c))
+        .last()
+        .map_or(pos, |(i, _)| i);
+    let end = src[pos..]
+        .find(|c| !is_word(c))
+        .map_or(src.len(), |len| pos + len);
+    (start, end)
+}
 
+/// Renders the line with the part at `column` (of `width` characters) underlined:
+///
+/// ```text
+///  --> 1:27
+///   |
+/// 1 | GET AAPL.close FOR LAST 2 dayz STEP 1 hour
+///   |                           ^^^^
+/// ```
+fn render_snippet(src_line: &str, line: usize, column: usize, width: usize) -> String {
+    let margin = " ".repeat(line.to_string().len());
+    format!(
+        "{margin}--> {line}:{column}\n{margin} |\n{line} | {src_line}\n{margin} | {}{}",
+        " ".repeat(column - 1),
+        "^".repeat(width)
+    )
+}
+
+/// Human-readable name of a grammar rule, as listed among expected tokens. Every rule
+/// is named, so none is reported by its name in the grammar (`not_expr`).
+pub(crate) fn describe_rule(rule: Rule) -> String {
+    let name = match rule {
+        Rule::EOI => "end of input",
+        Rule::WHITESPACE | Rule::NEWLINE => "whitespace",
+        Rule::COMMENT => "comment",
+        Rule::or_op => "OR",
+        Rule::and_op => "AND",
+        Rule::not_op => "NOT",
+        Rule::keyword_end => "end of keyword",
+        Rule::kw_get => "GET",
+        Rule::kw_for => "FOR",
+        Rule::kw_last => "LAST",
+        Rule::kw_from => "FROM",
+        Rule::kw_to => "TO",
+        Rule::kw_step => "STEP",
+        Rule::kw_resample => "RESAMPLE",
+        Rule::kw_where => "WHERE",
+        Rule::kw_order => "ORDER",
+        Rule::kw_by => "BY",
+        Rule::kw_asc => "ASC",
+        Rule::kw_desc => "DESC",
+        Rule::kw_limit => "LIMIT",
+        Rule::kw_offset => "OFFSET",
+        Rule::kw_summary => "SUMMARY",
+        Rule::kw_as => "AS",
+        Rule::kw_case => "CASE",
+        Rule::kw_when => "WHEN",
+        Rule::kw_then => "THEN",
+        Rule::kw_else => "ELSE",
+        Rule::kw_end => "END",
+        Rule::cmp_op => "comparison operator",
+        Rule::expr_op => "'+' or '-'",
+        Rule::term_op => "'*' or '/'",
+        Rule::metric => "metric",
+        Rule::exchange | Rule::bare_symbol | Rule::quoted_symbol | Rule::symbol => "symbol",
+        Rule::symbol_set => "symbol set",
+        Rule::universe_name | Rule::universe => "universe",
+        Rule::data => "symbol and metric",
+        Rule::expr
+        | Rule::and_expr
+        | Rule::not_expr
+        | Rule::cmp_expr
+        | Rule::sum
+        | Rule::term
+        | Rule::factor
+        | Rule::neg => "expression",
+        Rule::case_expr => "CASE expression",
+        Rule::case_when => "WHEN branch",
+        Rule::ident => "function name",
+        Rule::call => "function call",
+        Rule::number => "number",
+        Rule::alias => "alias",
+        Rule::column | Rule::single_column => "column",
+        Rule::expr_list => "columns",
+        Rule::value => "whole number",
+        Rule::time_unit => "time unit",
+        Rule::timestamp_value | Rule::timestamp => "timestamp",
+        Rule::for_clause => "FOR LAST",
+        Rule::from_clause => "FROM ... TO",
+        Rule::time_range => "time range",
+        Rule::step_clause => "STEP",
+        Rule::resample_clause => "RESAMPLE",
+        Rule::where_clause => "WHERE",
+        Rule::sort_key => "sort key",
+        Rule::sort_order => "ASC or DESC",
+        Rule::order_clause => "ORDER BY",
+        Rule::limit_clause => "LIMIT",
+        Rule::summary_clause => "SUMMARY",
+        Rule::query => "query",
+        Rule::single_clause => "clause",
+    };
+    name.to_string()
+}
+
+/// Suggestions appended to a message: `, did you mean 'close' or 'cls'?` (empty if none).
+pub(crate) fn did_you_mean(suggestions: &[String]) -> String {
+    match suggestions.is_empty() {
+        true => String::new(),
+        false => {
+            let quoted: Vec<String> = suggestions.iter().map(|s| format!("'{}'", s)).collect();
+            format!(", did you mean {}?", quoted.join(" or "))
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.kind)?;
+        if self.0.kind == ErrorKind::Syntax {
+            match (&self.0.token, &self.0.span) {
+                (_, Some(span)) if span.is_empty() => write!(f, ": unexpected end of input")?,
+                (Some(token), _) => write!(f, ": unexpected '{}'", token)?,
+                _ => {}
+            }
+            if !self.0.expected.is_empty() {
+                write!(f, ", expected {}", self.0.expected.join(", "))?;
+            }
+        }
+        write!(f, "{}", did_you_mean(&self.0.suggestions))?;
+        if let Some((line, column)) = self.0.line_col {
+            write!(f, " at line {}, column {}", line, column)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ErrorKind> for ParseError {
+    fn from(kind: ErrorKind) -> Self {
+        ParseError::new(kind)
+    }
+}
+
+impl From<pest::error::Error<Rule>> for ParseError {
+    fn from(err: pest::error::Error<Rule>) -> Self {
+        let mut error = ParseError::new(ErrorKind::Syntax);
+        error.0.span = Some(match err.location {
+            InputLocation::Pos(pos) => pos..pos,
+            InputLocation::Span((start, end)) => start..end,
+        });
+        if let ErrorVariant::ParsingError { positives, .. } = err.variant {
+            for rule in positives {
+                let name = describe_rule(rule);
+                if !error.0.expected.contains(&name) {
+                    error.0.expected.push(name);
+                }
+            }
+        }
+        error
+    }
+}
@@ -1,10 +1,20 @@
 mod builders;
+mod catalog;
 mod error;
 mod model;
 mod parser;
+mod query_builder;
+mod recovery;
 mod rule_parsers;
+mod suggest;
+mod universe;
+mod validate;
 
-pub use error::ParseError;
+pub use catalog::{MetricCatalog, MetricInfo};
+pub use error::{ErrorKind, ParseError};
 pub use model::*;
 pub use parser::*;
+pub use query_builder::{BuildError, QueryBuilder, Symbol, call, sym};
 pub use rule_parsers::*;
+pub use universe::Universes;
+pub use validate::{MAX_ROWS, Severity, ValidationIssue, validate, validate_at};
@@ -0,0 +1,64 @@
+use super::Expr;
+use std::fmt;
+
+/// Single item of the GET list: an expression with an optional alias (`expr AS name`).
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Column {
+    expr: Expr,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    alias: Option<String>,
+}
+
+impl Column {
+    pub fn new(expr: Expr, alias: Option<String>) -> Self {
+        Self { expr, alias }
+    }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    pub fn alias(&self) -> Option<&str> {
+        self.alias.as_deref()
+    }
+
+    /// Column header: the alias if given, otherwise the expression itself.
+    pub fn name(&self) -> String {
+        match &self.alias {
+            Some(alias) => alias.clone(),
+            None => self.expr.to_string(),
+        }
+    }
+}
+
+/// Alias of an aliased column expanded for a symbol of a set: the alias of the column
+/// followed by the symbol (`range_AAPL`), qualified by the exchange (`range_XNAS_AAPL`);
+/// characters not allowed in aliases are replaced by `_`.
+pub(crate) fn symbol_alias(alias: &str, exchange: Option<&str>, symbol: &str) -> String {
+    let mut name = format!("{}_", alias);
+    if let Some(exchange) = exchange {
+        name.push_str(exchange);
+        name.push('_');
+    }
+    name.extend(symbol.chars().map(|c| match c.is_ascii_alphanumeric() {
+        true => c,
+        false => '_',
+    }));
+    name
+}
+
+impl From<Expr> for Column {
+    fn from(expr: Expr) -> Self {
+        Self::new(expr, None)
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.alias {
+            Some(alias) => write!(f, "{} AS {}", self.expr, alias),
+            None => write!(f, "{}", self.expr),
+        }
+    }
+}
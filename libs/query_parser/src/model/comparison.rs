@@ -0,0 +1,66 @@
+use crate::error::ParseError;
+use std::fmt;
+
+/// Comparison operator of a condition, i.e. `>` in `AAPL.volume > 2000`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    pub fn cmpfn(&self) -> fn(f32, f32) -> bool {
+        use Comparison::*;
+        match self {
+            Eq => |a, b| a == b,
+            Ne => |a, b| a != b,
+            Lt => |a, b| a < b,
+            Le => |a, b| a <= b,
+            Gt => |a, b| a > b,
+            Ge => |a, b| a >= b,
+        }
+    }
+}
+
+impl TryFrom<&str> for Comparison {
+    type Error = ParseError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let op = match value {
+            "=" => Comparison::Eq,
+            "!=" | "<>" => Comparison::Ne,
+            "<" => Comparison::Lt,
+            "<=" => Comparison::Le,
+            ">" => Comparison::Gt,
+            ">=" => Comparison::Ge,
+            op => {
+                return Err(ParseError::invalid_value(
+                    op.to_string().into(),
+                    "cmp_op".into(),
+                ));
+            }
+        };
+        Ok(op)
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Comparison::*;
+        let op = match self {
+            Eq => "=",
+            Ne => "!=",
+            Lt => "<",
+            Le => "<=",
+            Gt => ">",
+            Ge => ">=",
+        };
+        write!(f, "{}", op)
+    }
+}
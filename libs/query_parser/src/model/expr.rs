@@ -1,20 +1,194 @@
 use std::fmt;
-use super::{Operator, SymbolMetric};
+use super::{Function, Operator, SymbolMetric};
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum Expr {
     Binary(Box<Expr>, Operator, Box<Expr>),
+    Call(Function, Vec<Expr>),
+    /// `CASE WHEN cond THEN value ... ELSE value END`; `IF(cond, a, b)` has a single branch.
+    /// Without ELSE the value is `NaN` when no condition holds.
+    Case(Vec<(Expr, Expr)>, Option<Box<Expr>>),
     Data(SymbolMetric),
-    Value(u32),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Value(f64),
 }
 
+impl Expr {
+    /// Number of time steps preceding the first row that must be fetched
+    /// to compute the expression, i.e. `SMA(x, 5)` needs 4 extra values.
+    pub fn lookback(&self) -> usize {
+        use Expr::*;
+        match self {
+            Binary(left, _op, right) => left.lookback().max(right.lookback()),
+            Call(func, args) => {
+                let inner = args.iter().map(Expr::lookback).max().unwrap_or(0);
+                inner.saturating_add(func.lookback(args))
+            }
+            Case(branches, default) => case_exprs(branches, default)
+                .map(Expr::lookback)
+                .max()
+                .unwrap_or(0),
+            Neg(expr) | Not(expr) => expr.lookback(),
+            Data(_) | Value(_) => 0,
+        }
+    }
+
+    /// Whether the expression has a single value for the whole range,
+    /// i.e. `AVG(x)` or `MAX(x) - MIN(x)`, but not `AVG(x) - x`.
+    pub fn is_aggregate(&self) -> bool {
+        self.contains_aggregate() && !self.has_series()
+    }
+
+    fn contains_aggregate(&self) -> bool {
+        use Expr::*;
+        match self {
+            Binary(left, _op, right) => left.contains_aggregate() || right.contains_aggregate(),
+            Call(func, args) => {
+                func.is_aggregate(args) || args.iter().any(Expr::contains_aggregate)
+            }
+            Case(branches, default) => case_exprs(branches, default).any(Expr::contains_aggregate),
+            Neg(expr) | Not(expr) => expr.contains_aggregate(),
+            Data(_) | Value(_) => false,
+        }
+    }
+
+    /// Whether the expression yields a boolean (1 or 0), i.e. `AAPL.close > AAPL.open`.
+    pub fn is_boolean(&self) -> bool {
+        use Expr::*;
+        match self {
+            Binary(_left, op, _right) => op.is_boolean(),
+            Not(_) => true,
+            Case(branches, default) => {
+                branches.iter().all(|(_cond, value)| value.is_boolean())
+                    && default.as_ref().is_none_or(|value| value.is_boolean())
+            }
+            _ => false,
+        }
+    }
+
+    /// Binding strength of the outermost operation, as for `Operator::precedence`;
+    /// unary minus binds stronger than any binary operator, and values, data,
+    /// calls and CASE expressions are atoms.
+    pub fn precedence(&self) -> u8 {
+        use Expr::*;
+        match self {
+            Binary(_left, op, _right) => op.precedence(),
+            Not(_) => NOT_PRECEDENCE,
+            // negative values are written with a unary minus
+            Neg(_) => NEG_PRECEDENCE,
+            Value(val) if val.is_sign_negative() => NEG_PRECEDENCE,
+            Call(..) | Case(..) | Data(_) | Value(_) => ATOM_PRECEDENCE,
+        }
+    }
+
+    /// The expression with each data replaced by `replace`, called in the order of the source.
+    pub(crate) fn map_data(&self, replace: &mut impl FnMut(&SymbolMetric) -> SymbolMetric) -> Expr {
+        use Expr::*;
+        match self {
+            Data(sm) => Data(replace(sm)),
+            Value(val) => Value(*val),
+            Neg(expr) => Neg(Box::new(expr.map_data(replace))),
+            Not(expr) => Not(Box::new(expr.map_data(replace))),
+            Binary(left, op, right) => {
+                let left = left.map_data(replace);
+                Binary(Box::new(left), *op, Box::new(right.map_data(replace)))
+            }
+            Call(func, args) => Call(*func, args.iter().map(|arg| arg.map_data(replace)).collect()),
+            Case(branches, default) => {
+                let branches = branches
+                    .iter()
+                    .map(|(cond, value)| (cond.map_data(replace), value.map_data(replace)))
+                    .collect();
+                Case(branches, default.as_ref().map(|value| Box::new(value.map_data(replace))))
+            }
+        }
+    }
+
+    /// Writes the expression as an operand which must bind at least as strong as
+    /// `min_precedence`, wrapping it in parentheses otherwise.
+    pub(crate) fn fmt_operand(&self, f: &mut fmt::Formatter, min_precedence: u8) -> fmt::Result {
+        match self.precedence() < min_precedence {
+            true => write!(f, "({})", self),
+            false => write!(f, "{}", self),
+        }
+    }
+
+    /// Whether the expression refers to data outside of any aggregate.
+    fn has_series(&self) -> bool {
+        use Expr::*;
+        match self {
+            Binary(left, _op, right) => left.has_series() || right.has_series(),
+            Call(func, args) if func.is_aggregate(args) => false,
+            Call(_func, args) => args.iter().any(Expr::has_series),
+            Case(branches, default) => case_exprs(branches, default).any(Expr::has_series),
+            Neg(expr) | Not(expr) => expr.has_series(),
+            Data(_) => true,
+            Value(_) => false,
+        }
+    }
+}
+
+const NOT_PRECEDENCE: u8 = 3;
+const NEG_PRECEDENCE: u8 = 7;
+const ATOM_PRECEDENCE: u8 = 8;
+
+/// Conditions, values and the default of a CASE expression.
+fn case_exprs<'a>(
+    branches: &'a [(Expr, Expr)],
+    default: &'a Option<Box<Expr>>,
+) -> impl Iterator<Item = &'a Expr> {
+    branches
+        .iter()
+        .flat_map(|(cond, value)| [cond, value])
+        .chain(default.as_deref())
+}
+
+/// Writes the expression in the canonical form: keywords in upper case, single spaces
+/// around binary operators and only the parentheses needed to parse it back
+/// into the same tree (operators of the same precedence group to the left,
+/// comparisons don't chain).
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use Expr::*;
         match self {
-            Binary(left, op, right) => write!(f, "{} {} {}", left, op, right),
+            Binary(left, op, right) => {
+                let precedence = op.precedence();
+                let left_precedence = match op {
+                    Operator::Compare(_) => precedence + 1,
+                    _ => precedence,
+                };
+                left.fmt_operand(f, left_precedence)?;
+                write!(f, " {} ", op)?;
+                right.fmt_operand(f, precedence + 1)
+            }
+            Call(func, args) => {
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "{}({})", func, args.join(", "))
+            }
+            Case(branches, default) => {
+                write!(f, "CASE")?;
+                for (cond, value) in branches {
+                    write!(f, " WHEN {} THEN {}", cond, value)?;
+                }
+                if let Some(default) = default {
+                    write!(f, " ELSE {}", default)?;
+                }
+                write!(f, " END")
+            }
             Data(symbol) => write!(f, "{}", symbol),
-            Value(val) => write!(f, "{}", val)
+            // `--` would start a comment, so a negated negation is in parentheses
+            Neg(expr) => {
+                write!(f, "-")?;
+                expr.fmt_operand(f, ATOM_PRECEDENCE)
+            }
+            Not(expr) => {
+                write!(f, "NOT ")?;
+                expr.fmt_operand(f, NOT_PRECEDENCE)
+            }
+            Value(val) => write!(f, "{}", val),
         }
     }
 }
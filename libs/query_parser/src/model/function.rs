@@ -0,0 +1,187 @@
+use super::Expr;
+use crate::{error::ParseError, validate::MAX_ROWS};
+use std::{fmt, ops::RangeInclusive};
+
+/// Built-in functions callable from expressions, i.e. `SQRT(AAPL.volume)`.
+/// Names are case-insensitive.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "SCREAMING_SNAKE_CASE"))]
+#[non_exhaustive]
+pub enum Function {
+    Abs,
+    Sqrt,
+    Ln,
+    Log10,
+    Exp,
+    Pow,
+    Round,
+    Floor,
+    Ceil,
+    Min,
+    Max,
+    Sma,
+    Ema,
+    Stddev,
+    RollingMax,
+    RollingMin,
+    Diff,
+    PctChange,
+    Lag,
+    Lead,
+    Cumsum,
+    Cumprod,
+    Avg,
+    Sum,
+    Count,
+    Median,
+    Percentile,
+}
+
+impl Function {
+    /// Number of arguments the function accepts.
+    /// `MIN` and `MAX` take one series (aggregate) or two (element-wise).
+    pub fn arity(&self) -> RangeInclusive<usize> {
+        use Function::*;
+        match self {
+            Abs | Sqrt | Ln | Log10 | Exp | Round | Floor | Ceil => 1..=1,
+            Pow => 2..=2,
+            Min | Max => 1..=2,
+            Sma | Ema | Stddev | RollingMax | RollingMin => 2..=2,
+            Diff | PctChange | Cumsum | Cumprod => 1..=1,
+            Lag | Lead => 2..=2,
+            Avg | Sum | Count | Median => 1..=1,
+            Percentile => 2..=2,
+        }
+    }
+
+    /// Whether the call reduces the whole series to a single value, i.e. `AVG(x)` or `MIN(x)`.
+    pub fn is_aggregate(&self, args: &[Expr]) -> bool {
+        use Function::*;
+        match self {
+            Avg | Sum | Count | Median | Percentile => true,
+            Min | Max => args.len() == 1,
+            _ => false,
+        }
+    }
+
+    /// Whether the last argument is a period (a positive integer literal, i.e. window size)
+    /// rather than a series.
+    pub fn takes_period(&self) -> bool {
+        use Function::*;
+        matches!(
+            self,
+            Sma | Ema | Stddev | RollingMax | RollingMin | Lag | Lead
+        )
+    }
+
+    /// Number of preceding time steps the function needs (on top of its arguments)
+    /// to produce a value for the first row.
+    pub fn lookback(&self, args: &[Expr]) -> usize {
+        use Function::*;
+        let period = self.period(args).unwrap_or(1);
+        match self {
+            Sma | Ema | Stddev | RollingMax | RollingMin => period.saturating_sub(1),
+            Lag => period,
+            Diff | PctChange => 1,
+            _ => 0,
+        }
+    }
+
+    /// Smallest valid period: a standard deviation needs at least two values.
+    pub fn min_period(&self) -> usize {
+        match self {
+            Function::Stddev => 2,
+            _ => 1,
+        }
+    }
+
+    /// Returns the period from the call arguments, if the function takes one
+    /// and it is a valid literal: an integer from `min_period` up to `MAX_ROWS`.
+    pub fn period(&self, args: &[Expr]) -> Option<usize> {
+        if !self.takes_period() {
+            return None;
+        }
+        let range = self.min_period() as f64..=MAX_ROWS as f64;
+        match args.last() {
+            Some(Expr::Value(val)) if range.contains(val) && val.fract() == 0.0 => {
+                Some(*val as usize)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<&str> for Function {
+    type Error = ParseError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let val = match value.to_ascii_lowercase().as_str() {
+            "abs" => Function::Abs,
+            "sqrt" => Function::Sqrt,
+            "ln" => Function::Ln,
+            "log10" => Function::Log10,
+            "exp" => Function::Exp,
+            "pow" => Function::Pow,
+            "round" => Function::Round,
+            "floor" => Function::Floor,
+            "ceil" => Function::Ceil,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            "sma" => Function::Sma,
+            "ema" => Function::Ema,
+            "stddev" => Function::Stddev,
+            "rolling_max" => Function::RollingMax,
+            "rolling_min" => Function::RollingMin,
+            "diff" => Function::Diff,
+            "pct_change" => Function::PctChange,
+            "lag" => Function::Lag,
+            "lead" => Function::Lead,
+            "cumsum" => Function::Cumsum,
+            "cumprod" => Function::Cumprod,
+            "avg" => Function::Avg,
+            "sum" => Function::Sum,
+            "count" => Function::Count,
+            "median" => Function::Median,
+            "percentile" => Function::Percentile,
+            _ => return Err(ParseError::unknown_function(value.to_string().into())),
+        };
+        Ok(val)
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Function::*;
+        let val = match self {
+            Abs => "ABS",
+            Sqrt => "SQRT",
+            Ln => "LN",
+            Log10 => "LOG10",
+            Exp => "EXP",
+            Pow => "POW",
+            Round => "ROUND",
+            Floor => "FLOOR",
+            Ceil => "CEIL",
+            Min => "MIN",
+            Max => "MAX",
+            Sma => "SMA",
+            Ema => "EMA",
+            Stddev => "STDDEV",
+            RollingMax => "ROLLING_MAX",
+            RollingMin => "ROLLING_MIN",
+            Diff => "DIFF",
+            PctChange => "PCT_CHANGE",
+            Lag => "LAG",
+            Lead => "LEAD",
+            Cumsum => "CUMSUM",
+            Cumprod => "CUMPROD",
+            Avg => "AVG",
+            Sum => "SUM",
+            Count => "COUNT",
+            Median => "MEDIAN",
+            Percentile => "PERCENTILE",
+        };
+        write!(f, "{}", val)
+    }
+}
@@ -1,50 +1,40 @@
-use crate::error::ParseError;
-use std::fmt;
+use std::{borrow::Cow, fmt};
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
-#[non_exhaustive]
-pub enum Metric {
-    Volume,
-    Max,
-    Min,
-    Open,
-    Close,
-    Avg,
-}
+/// Name of a metric of a symbol, i.e. `close` or `vwap`. The parser accepts any
+/// identifier; which metrics exist is told by a [`MetricCatalog`](crate::MetricCatalog).
+/// The well-known metrics are constants.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Metric(Cow<'static, str>);
+
+impl Metric {
+    pub const VOLUME: Metric = Metric::from_static("volume");
+    pub const MAX: Metric = Metric::from_static("max");
+    pub const MIN: Metric = Metric::from_static("min");
+    pub const OPEN: Metric = Metric::from_static("open");
+    pub const CLOSE: Metric = Metric::from_static("close");
+    pub const AVG: Metric = Metric::from_static("avg");
+
+    /// Names of the well-known metrics, as written in queries.
+    pub const NAMES: &'static [&'static str] = &["volume", "max", "min", "open", "close", "avg"];
 
-impl TryFrom<&str> for Metric {
-    type Error = ParseError;
+    pub fn new(name: impl Into<Cow<'static, str>>) -> Self {
+        Self(name.into())
+    }
+
+    /// Metric with a constant name, i.e. `const VWAP: Metric = Metric::from_static("vwap");`.
+    pub const fn from_static(name: &'static str) -> Self {
+        Self(Cow::Borrowed(name))
+    }
 
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
-        let val = match value {
-            "volume" => Metric::Volume,
-            "max" => Metric::Max,
-            "min" => Metric::Min,
-            "open" => Metric::Open,
-            "close" => Metric::Close,
-            "avg" => Metric::Avg,
-            other => {
-                return Err(ParseError::InvalidValue(
-                    other.to_string().into(),
-                    "metric".into(),
-                ));
-            }
-        };
-        Ok(val)
+    pub fn name(&self) -> &str {
+        &self.0
     }
 }
 
 impl fmt::Display for Metric {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use Metric::*;
-        let val = match self {
-            Volume => "volume",
-            Max => "max",
-            Min => "min",
-            Open => "open",
-            Close => "close",
-            Avg => "avg",
-        };
-        write!(f, "{}", val)
+        write!(f, "{}", self.0)
     }
 }
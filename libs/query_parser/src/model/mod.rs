@@ -1,12 +1,21 @@
+mod column;
+mod comparison;
 mod expr;
+mod function;
 mod metric;
 mod operator;
+mod order_by;
 mod query;
+mod rollup;
 mod symbol_metric;
+mod time_range;
 mod time_spec;
 mod time_unit;
 
 pub use {
-    expr::Expr, metric::Metric, operator::Operator, query::Query, symbol_metric::SymbolMetric,
-    time_spec::TimeSpec, time_unit::TimeUnit,
+    column::Column, comparison::Comparison, expr::Expr, function::Function,
+    metric::Metric, operator::{Operator, is_true}, order_by::{OrderBy, SortKey, SortOrder}, query::Query,
+    rollup::Rollup, symbol_metric::{SymbolMetric, SymbolSet}, time_range::TimeRange, time_spec::TimeSpec,
+    time_unit::TimeUnit,
 };
+pub(crate) use {column::symbol_alias, symbol_metric::split_exchange};
@@ -1,41 +1,85 @@
+use super::Comparison;
 use crate::error::ParseError;
 use std::fmt;
 
 #[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum Operator {
     Add,
     Sub,
     Mul,
     Div,
+    Compare(Comparison),
+    And,
+    Or,
 }
 
 impl Operator {
+    /// Comparisons and logical operators yield 1 (true) or 0 (false).
+    /// Any value other than 0 and `NaN` is true.
     pub fn opfn(&self) -> fn(f32, f32) -> f32 {
+        use Comparison::*;
         use Operator::*;
         match self {
             Add => |a, b| a + b,
             Sub => |a, b| a - b,
             Mul => |a, b| a * b,
             Div => |a, b| a / b,
+            Compare(Eq) => |a, b| bool_value(a == b),
+            Compare(Ne) => |a, b| bool_value(a != b),
+            Compare(Lt) => |a, b| bool_value(a < b),
+            Compare(Le) => |a, b| bool_value(a <= b),
+            Compare(Gt) => |a, b| bool_value(a > b),
+            Compare(Ge) => |a, b| bool_value(a >= b),
+            And => |a, b| bool_value(is_true(a) && is_true(b)),
+            Or => |a, b| bool_value(is_true(a) || is_true(b)),
         }
     }
+
+    /// Binding strength of the operator: `OR` binds weakest, then `AND`, `NOT` (see
+    /// `Expr::precedence`), comparisons, `+`/`-` and `*`/`/`.
+    pub fn precedence(&self) -> u8 {
+        use Operator::*;
+        match self {
+            Or => 1,
+            And => 2,
+            Compare(_) => 4,
+            Add | Sub => 5,
+            Mul | Div => 6,
+        }
+    }
+
+    /// Whether the operator yields a boolean (comparisons and logical operators).
+    pub fn is_boolean(&self) -> bool {
+        matches!(self, Operator::Compare(_) | Operator::And | Operator::Or)
+    }
+}
+
+/// Truthiness of a value, of a condition or an operand of AND/OR: anything other
+/// than 0 and `NaN` is true.
+pub fn is_true(value: f32) -> bool {
+    value != 0.0 && !value.is_nan()
+}
+
+fn bool_value(value: bool) -> f32 {
+    if value { 1.0 } else { 0.0 }
 }
 
 impl TryFrom<&str> for Operator {
     type Error = ParseError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        let op = match value {
+        let op = match value.to_ascii_uppercase().as_str() {
             "+" => Operator::Add,
             "-" => Operator::Sub,
             "*" => Operator::Mul,
             "/" => Operator::Div,
-            op => {
-                return Err(ParseError::InvalidValue(
-                    op.to_string().into(),
-                    "operator".into(),
-                ));
-            }
+            "AND" => Operator::And,
+            "OR" => Operator::Or,
+            op => Operator::Compare(Comparison::try_from(op).map_err(|_| {
+                ParseError::invalid_value(op.to_string().into(), "operator".into())
+            })?),
         };
         Ok(op)
     }
@@ -49,6 +93,9 @@ impl fmt::Display for Operator {
             Sub => "-",
             Mul => "*",
             Div => "/",
+            Compare(cmp) => return write!(f, "{}", cmp),
+            And => "AND",
+            Or => "OR",
         };
         write!(f, "{}", op)
     }
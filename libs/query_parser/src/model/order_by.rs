@@ -0,0 +1,63 @@
+use super::Expr;
+use std::fmt;
+
+/// ORDER BY clause: the rows are sorted by an expression or by a column alias.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OrderBy {
+    key: SortKey,
+    order: SortOrder,
+}
+
+/// What the rows are sorted by.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SortKey {
+    Expr(Expr),
+    Alias(String),
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl OrderBy {
+    pub fn new(key: SortKey, order: SortOrder) -> Self {
+        Self { key, order }
+    }
+
+    pub fn key(&self) -> &SortKey {
+        &self.key
+    }
+
+    pub fn order(&self) -> SortOrder {
+        self.order
+    }
+
+    /// The sort expression, unless the rows are sorted by an alias of a column.
+    pub fn expr(&self) -> Option<&Expr> {
+        match &self.key {
+            SortKey::Expr(expr) => Some(expr),
+            SortKey::Alias(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for OrderBy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.key {
+            SortKey::Expr(expr) => write!(f, "{}", expr)?,
+            SortKey::Alias(alias) => write!(f, "{}", alias)?,
+        }
+        match self.order {
+            SortOrder::Asc => Ok(()),
+            SortOrder::Desc => write!(f, " DESC"),
+        }
+    }
+}
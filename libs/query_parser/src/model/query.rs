@@ -1,48 +1,185 @@
-use super::{Expr, TimeSpec};
+use super::{Column, Expr, OrderBy, TimeRange, TimeSpec};
+use chrono::{DateTime, Utc};
 use std::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Query {
-    expressions: Vec<Expr>,
-    for_clause: TimeSpec,
+    columns: Vec<Column>,
+    range: TimeRange,
     step: TimeSpec,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    resample: Option<TimeSpec>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    filter: Option<Expr>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    order_by: Option<OrderBy>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    limit: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "is_zero"))]
+    offset: usize,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "is_false"))]
+    summary: bool,
 }
 
 impl Query {
-    pub fn new(expressions: Vec<Expr>, for_clause: TimeSpec, step_clause: TimeSpec) -> Self {
+    pub fn new(columns: Vec<Column>, range: TimeRange, step_clause: TimeSpec) -> Self {
         Self {
-            expressions,
-            for_clause,
+            columns,
+            range,
             step: step_clause,
+            resample: None,
+            filter: None,
+            order_by: None,
+            limit: None,
+            offset: 0,
+            summary: false,
         }
     }
 
-    pub fn expressions(&self) -> &Vec<Expr> {
-        &self.expressions
+    /// Replaces the columns (GET list).
+    pub fn with_columns(mut self, columns: Vec<Column>) -> Self {
+        self.columns = columns;
+        self
     }
 
-    pub fn for_clause(&self) -> &TimeSpec {
-        &self.for_clause
+    /// Sets the size of the bars fetched from upstream and merged into steps (RESAMPLE clause).
+    pub fn with_resample(mut self, resample: Option<TimeSpec>) -> Self {
+        self.resample = resample;
+        self
+    }
+
+    /// Sets the condition rows must meet to be included in the result (WHERE clause).
+    pub fn with_filter(mut self, filter: Option<Expr>) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Sets the order of the result rows (ORDER BY clause); chronological if not set.
+    pub fn with_order_by(mut self, order_by: Option<OrderBy>) -> Self {
+        self.order_by = order_by;
+        self
+    }
+
+    /// Sets the maximum number of rows and the number of leading rows to skip (LIMIT clause).
+    pub fn with_limit(mut self, limit: Option<usize>, offset: usize) -> Self {
+        self.limit = limit;
+        self.offset = offset;
+        self
+    }
+
+    /// Sets whether the result should include summary rows (SUMMARY clause).
+    pub fn with_summary(mut self, summary: bool) -> Self {
+        self.summary = summary;
+        self
+    }
+
+    pub fn columns(&self) -> &Vec<Column> {
+        &self.columns
+    }
+
+    pub fn expressions(&self) -> impl Iterator<Item = &Expr> {
+        self.columns.iter().map(|c| c.expr())
+    }
+
+    /// Number of time steps preceding the range needed to compute the columns
+    /// and the WHERE and ORDER BY expressions (see `Expr::lookback`).
+    pub fn lookback(&self) -> usize {
+        let order_by = self.order_by.as_ref().and_then(OrderBy::expr);
+        self.expressions()
+            .chain(self.filter.as_ref())
+            .chain(order_by)
+            .map(Expr::lookback)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn range(&self) -> &TimeRange {
+        &self.range
     }
 
     pub fn step(&self) -> &TimeSpec {
         &self.step
     }
 
-    pub fn rows_count(&self) -> usize {
-        (self.for_clause.to_seconds() / self.step.to_seconds()).max(1) as usize
+    pub fn resample(&self) -> Option<&TimeSpec> {
+        self.resample.as_ref()
+    }
+
+    /// Number of fetched bars merged into a single step, if the query is resampled.
+    pub fn resample_factor(&self) -> Option<usize> {
+        self.resample
+            .as_ref()
+            .and_then(|bar| self.step.to_seconds().checked_div(bar.to_seconds()))
+            .map(|factor| factor as usize)
+    }
+
+    pub fn filter(&self) -> Option<&Expr> {
+        self.filter.as_ref()
+    }
+
+    pub fn order_by(&self) -> Option<&OrderBy> {
+        self.order_by.as_ref()
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn summary(&self) -> bool {
+        self.summary
+    }
+
+    /// Number of steps in the range when the query is executed at `now` (at least 1,
+    /// see `validate` for queries whose step is zero or longer than the range).
+    pub fn rows_count(&self, now: DateTime<Utc>) -> usize {
+        let rows = self.range.length_at(now).checked_div(self.step.to_seconds());
+        rows.unwrap_or(0).max(1) as usize
     }
 }
 
 impl fmt::Display for Query {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let expr: Vec<String> = self.expressions.iter().map(|e| e.to_string()).collect();
+        let expr: Vec<String> = self.columns.iter().map(|c| c.to_string()).collect();
         write!(
             f,
-            "GET {} FOR {} STEP {}",
+            "GET {} {} STEP {}",
             expr.join(", "),
-            self.for_clause,
+            self.range,
             self.step
-        )
+        )?;
+        if let Some(resample) = &self.resample {
+            write!(f, " RESAMPLE {}", resample)?;
+        }
+        if let Some(filter) = &self.filter {
+            write!(f, " WHERE {}", filter)?;
+        }
+        if let Some(order_by) = &self.order_by {
+            write!(f, " ORDER BY {}", order_by)?;
+        }
+        if let Some(limit) = self.limit {
+            write!(f, " LIMIT {}", limit)?;
+            if self.offset > 0 {
+                write!(f, " OFFSET {}", self.offset)?;
+            }
+        }
+        if self.summary {
+            write!(f, " SUMMARY")?;
+        }
+        Ok(())
     }
 }
+
+#[cfg(feature = "serde")]
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+#[cfg(feature = "serde")]
+fn is_false(value: &bool) -> bool {
+    !*value
+}
@@ -0,0 +1,33 @@
+use std::fmt;
+
+/// How values of a metric are combined when several bars are merged into one
+/// (see the RESAMPLE clause).
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Rollup {
+    /// Value of the first bar in the bucket.
+    First,
+    /// Value of the last bar in the bucket.
+    Last,
+    Max,
+    Min,
+    Sum,
+    /// Mean of the values weighted by the volume of each bar.
+    VolumeWeighted,
+}
+
+impl fmt::Display for Rollup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Rollup::*;
+        let val = match self {
+            First => "first",
+            Last => "last",
+            Max => "max",
+            Min => "min",
+            Sum => "sum",
+            VolumeWeighted => "volume-weighted",
+        };
+        write!(f, "{}", val)
+    }
+}
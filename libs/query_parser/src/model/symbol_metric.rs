@@ -1,31 +1,169 @@
-use std::fmt;
 use super::Metric;
+use std::{borrow::Cow, fmt, ops::Range};
 
-#[derive(Debug, PartialEq, Clone)]
+/// Metric of a symbol, i.e. `AAPL.close`. The symbol may be qualified by
+/// the exchange it is traded on (`XNAS:AAPL.close`), or the data may be of a set
+/// of symbols (`{AAPL, MSFT}.close`, `@portfolio.close`) to be expanded before the query runs.
+/// Parsed data keeps its position in the source, which takes no part in comparisons.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SymbolMetric {
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    exchange: Option<String>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "String::is_empty")
+    )]
     symbol: String,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    set: Option<SymbolSet>,
     metric: Metric,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    span: Option<Range<usize>>,
+}
+
+/// Symbols the data of a column refers to at once; the column is expanded into
+/// a column per symbol. Symbols may be qualified by the exchange (`XNAS:AAPL`).
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SymbolSet {
+    /// Symbols listed in the query: `{AAPL, XNAS:MSFT}`.
+    List(Vec<String>),
+    /// Universe defined by the service running the query: `@portfolio`.
+    Universe(String),
 }
 
 impl SymbolMetric {
     pub fn new(symbol: &str, metric: Metric) -> Self {
         Self {
+            exchange: None,
             symbol: symbol.to_string(),
+            set: None,
+            metric,
+            span: None,
+        }
+    }
+
+    /// Metric of each symbol of the set (`{AAPL, MSFT}.metric`, `@name.metric`).
+    pub fn of_set(set: SymbolSet, metric: Metric) -> Self {
+        Self {
+            exchange: None,
+            symbol: String::new(),
+            set: Some(set),
             metric,
+            span: None,
         }
     }
 
+    /// Metric of the symbols of the universe `name` (`@name.metric`).
+    pub fn universe(name: &str, metric: Metric) -> Self {
+        Self::of_set(SymbolSet::Universe(name.to_string()), metric)
+    }
+
+    pub fn with_exchange(mut self, exchange: Option<&str>) -> Self {
+        self.exchange = exchange.map(str::to_string);
+        self
+    }
+
+    pub fn exchange(&self) -> Option<&str> {
+        self.exchange.as_deref()
+    }
+
+    /// The symbol, empty for data of a set.
     pub fn symbol(&self) -> &str {
         &self.symbol
     }
 
-    pub fn metric(&self) -> Metric {
-        self.metric
+    /// The set of symbols the data is of, if not of a single symbol.
+    pub fn set(&self) -> Option<&SymbolSet> {
+        self.set.as_ref()
+    }
+
+    /// The symbol with its exchange, if any (`XNAS:AAPL`), telling apart
+    /// the same ticker on different exchanges. For data of a set, the set (`@portfolio`).
+    pub fn qualified_symbol(&self) -> Cow<'_, str> {
+        match (&self.exchange, &self.set) {
+            (_, Some(set)) => Cow::Owned(set.to_string()),
+            (Some(exchange), None) => Cow::Owned(format!("{}:{}", exchange, self.symbol)),
+            (None, None) => Cow::Borrowed(&self.symbol),
+        }
+    }
+
+    pub fn metric(&self) -> &Metric {
+        &self.metric
+    }
+
+    /// Byte range of the data in the source it was parsed from.
+    pub fn span(&self) -> Option<Range<usize>> {
+        self.span.clone()
+    }
+
+    pub(crate) fn with_span(mut self, span: Option<Range<usize>>) -> Self {
+        self.span = span;
+        self
+    }
+}
+
+impl PartialEq for SymbolMetric {
+    fn eq(&self, other: &Self) -> bool {
+        self.exchange == other.exchange
+            && self.symbol == other.symbol
+            && self.set == other.set
+            && self.metric == other.metric
+    }
+}
+
+/// Splits a symbol qualified by its exchange (`XNAS:AAPL`) into the exchange and the symbol.
+pub(crate) fn split_exchange(symbol: &str) -> (Option<&str>, &str) {
+    match symbol.split_once(':') {
+        Some((exchange, symbol)) => (Some(exchange), symbol),
+        None => (None, symbol),
+    }
+}
+
+/// Writes the symbol quoted if it is not alphanumeric: `XNYS:"BRK.B"`.
+fn fmt_symbol(f: &mut fmt::Formatter, exchange: Option<&str>, symbol: &str) -> fmt::Result {
+    if let Some(exchange) = exchange {
+        write!(f, "{}:", exchange)?;
+    }
+    match !symbol.is_empty() && symbol.chars().all(|c| c.is_ascii_alphanumeric()) {
+        true => write!(f, "{}", symbol),
+        false => write!(f, "\"{}\"", symbol),
     }
 }
 
 impl fmt::Display for SymbolMetric {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}.{}", self.symbol, self.metric)
+        match &self.set {
+            Some(set) => write!(f, "{}", set)?,
+            None => fmt_symbol(f, self.exchange.as_deref(), &self.symbol)?,
+        }
+        write!(f, ".{}", self.metric)
+    }
+}
+
+impl fmt::Display for SymbolSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolSet::List(symbols) => {
+                write!(f, "{{")?;
+                for (i, symbol) in symbols.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    let (exchange, symbol) = split_exchange(symbol);
+                    fmt_symbol(f, exchange, symbol)?;
+                }
+                write!(f, "}}")
+            }
+            SymbolSet::Universe(name) => write!(f, "@{}", name),
+        }
     }
 }
@@ -0,0 +1,44 @@
+use super::TimeSpec;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fmt;
+
+/// Time window covered by a query: either relative to the moment the query
+/// is executed (`FOR LAST 3 days`) or fixed (`FROM '...' TO '...'`).
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TimeRange {
+    Last(TimeSpec),
+    Between(DateTime<Utc>, DateTime<Utc>),
+}
+
+impl TimeRange {
+    /// Start and end of the range when the query is executed at `now`. Relative ranges
+    /// end at `now` and start with calendar-aware months (see `TimeSpec::before`).
+    pub fn bounds(&self, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        match self {
+            TimeRange::Last(spec) => (spec.before(now).unwrap_or(DateTime::<Utc>::MIN_UTC), now),
+            TimeRange::Between(from, to) => (*from, *to),
+        }
+    }
+
+    /// Length of the range in seconds when the query is executed at `now`.
+    pub fn length_at(&self, now: DateTime<Utc>) -> u64 {
+        let (from, to) = self.bounds(now);
+        (to - from).num_seconds().max(0) as u64
+    }
+}
+
+impl fmt::Display for TimeRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeRange::Last(spec) => write!(f, "FOR LAST {}", spec),
+            TimeRange::Between(from, to) => write!(
+                f,
+                "FROM '{}' TO '{}'",
+                from.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                to.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+            ),
+        }
+    }
+}
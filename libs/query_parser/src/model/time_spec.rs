@@ -1,8 +1,10 @@
 use super::TimeUnit;
 use crate::error::ParseError;
+use chrono::{DateTime, Months, TimeDelta, Utc};
 use std::{fmt, time::Duration};
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TimeSpec {
     value: u32,
     unit: TimeUnit,
@@ -22,9 +24,16 @@ impl TimeSpec {
     }
 
     pub fn to_seconds(&self) -> u64 {
+        self.unit.seconds() * self.value as u64
+    }
+
+    /// Returns the point in time that is this time span before `time`.
+    /// Months are calendar months (one month before 31 March is the end of February),
+    /// all other units have fixed length.
+    pub fn before(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
         match self.unit {
-            TimeUnit::Hour => 3600 * self.value as u64,
-            TimeUnit::Day => 86400 * self.value as u64,
+            TimeUnit::Month => time.checked_sub_months(Months::new(self.value)),
+            _ => time.checked_sub_signed(TimeDelta::seconds(self.to_seconds() as i64)),
         }
     }
 }
@@ -35,12 +44,12 @@ impl TryFrom<(&str, &str)> for TimeSpec {
     fn try_from(value: (&str, &str)) -> Result<Self, Self::Error> {
         let spec = Self {
             value: String::from(value.0).parse().map_err(|_| {
-                ParseError::InvalidValue(value.0.to_string().into(), "time_spec".into())
+                ParseError::invalid_value(value.0.to_string().into(), "time_spec".into())
             })?,
             unit: TimeUnit::try_from(value.1)?,
         };
         if spec.value < 1 {
-            return Err(ParseError::InvalidValue(
+            return Err(ParseError::invalid_value(
                 value.0.to_string().into(),
                 "time_spec".into(),
             ));
@@ -63,9 +72,6 @@ impl fmt::Display for TimeSpec {
 
 impl From<&TimeSpec> for Duration {
     fn from(tr: &TimeSpec) -> Self {
-        match tr.unit {
-            TimeUnit::Hour => Duration::from_secs(tr.to_seconds()),
-            TimeUnit::Day => Duration::from_secs(tr.to_seconds()),
-        }
+        Duration::from_secs(tr.to_seconds())
     }
 }
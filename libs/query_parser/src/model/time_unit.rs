@@ -1,10 +1,38 @@
-use crate::error::ParseError;
+use crate::{error::ParseError, suggest::suggest};
 use std::fmt;
 
 #[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum TimeUnit {
+    Second,
+    Minute,
     Hour,
     Day,
+    Week,
+    Month,
+}
+
+impl TimeUnit {
+    /// Names of all units, as written in queries (singular and plural).
+    pub const NAMES: &'static [&'static str] = &[
+        "second", "seconds", "minute", "minutes", "hour", "hours", "day", "days", "week", "weeks",
+        "month", "months",
+    ];
+
+    /// Nominal length of the unit in seconds. Months are counted as 30 days;
+    /// use `TimeSpec::before` where calendar months matter.
+    pub fn seconds(&self) -> u64 {
+        use TimeUnit::*;
+        match self {
+            Second => 1,
+            Minute => 60,
+            Hour => 3600,
+            Day => 86400,
+            Week => 7 * 86400,
+            Month => 30 * 86400,
+        }
+    }
 }
 
 impl TryFrom<&str> for TimeUnit {
@@ -12,14 +40,23 @@ impl TryFrom<&str> for TimeUnit {
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         let val = match value {
-            "day" => TimeUnit::Day,
-            "days" => TimeUnit::Day,
+            "second" => TimeUnit::Second,
+            "seconds" => TimeUnit::Second,
+            "minute" => TimeUnit::Minute,
+            "minutes" => TimeUnit::Minute,
             "hour" => TimeUnit::Hour,
             "hours" => TimeUnit::Hour,
+            "day" => TimeUnit::Day,
+            "days" => TimeUnit::Day,
+            "week" => TimeUnit::Week,
+            "weeks" => TimeUnit::Week,
+            "month" => TimeUnit::Month,
+            "months" => TimeUnit::Month,
             other => {
-                return Err(ParseError::InvalidValue(
+                return Err(ParseError::unknown(
+                    "time unit".into(),
                     other.to_string().into(),
-                    "time_unit".into(),
+                    suggest(other, Self::NAMES),
                 ));
             }
         };
@@ -34,8 +71,12 @@ impl fmt::Display for TimeUnit {
             f,
             "{}",
             match self {
+                Second => "second",
+                Minute => "minute",
                 Hour => "hour",
                 Day => "day",
+                Week => "week",
+                Month => "month",
             }
         )
     }
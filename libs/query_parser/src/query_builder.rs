@@ -0,0 +1,429 @@
+//! Building queries in code, without writing and parsing their source:
+//!
+//! ```
+//! use query_parser::{Query, SortOrder, TimeUnit::*, call, parse_query, sym, Function::Sma};
+//!
+//! let query = Query::builder()
+//!     .get_as(sym("AAPL").close() / 1000, "close_k")
+//!     .get(call(Sma, [sym("AAPL").close(), 20.into()]))
+//!     .for_last(30, Day)
+//!     .step(1, Day)
+//!     .filter(sym("AAPL").volume().gt(1000) & !sym("AAPL").close().lt(10))
+//!     .order_by_alias("close_k", SortOrder::Desc)
+//!     .limit(5)
+//!     .build()
+//!     .unwrap();
+//!
+//! let src = "GET AAPL.close / 1000 AS close_k, SMA(AAPL.close, 20) FOR LAST 30 days STEP 1 day \
+//!            WHERE AAPL.volume > 1000 AND NOT AAPL.close < 10 ORDER BY close_k DESC LIMIT 5";
+//! assert_eq!(parse_query(src).unwrap(), query);
+//! ```
+
+use std::{
+    borrow::Cow,
+    ops::{Add, BitAnd, BitOr, Div, Mul, Neg, Not, Sub},
+};
+
+use chrono::{DateTime, Utc};
+
+use super::{
+    catalog::MetricCatalog,
+    model::{
+        Column, Comparison, Expr, Function, Metric, Operator, OrderBy, Query, SortKey, SortOrder,
+        SymbolMetric, TimeRange, TimeSpec, TimeUnit,
+    },
+    validate::{ValidationIssue, validate},
+};
+
+/// Error of building a query with [`QueryBuilder`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum BuildError {
+    #[error("Missing {0}")]
+    Missing(Cow<'static, str>),
+
+    #[error("Filter {0} is not a condition")]
+    InvalidFilter(String),
+
+    /// Issues found by validating the query, at least one of them an error.
+    #[error("Invalid query: {}", ValidationIssue::errors_message(.0))]
+    Invalid(Vec<ValidationIssue>),
+}
+
+/// Builder of a [`Query`], clause by clause. The query is validated by
+/// [`build`](QueryBuilder::build) as a parsed one would be.
+#[derive(Debug, Default, Clone)]
+pub struct QueryBuilder {
+    columns: Vec<Column>,
+    range: Option<TimeRange>,
+    step: Option<TimeSpec>,
+    resample: Option<TimeSpec>,
+    filter: Option<Expr>,
+    order_by: Option<OrderBy>,
+    limit: Option<usize>,
+    offset: usize,
+    summary: bool,
+}
+
+impl Query {
+    pub fn builder() -> QueryBuilder {
+        QueryBuilder::default()
+    }
+}
+
+impl QueryBuilder {
+    /// Adds a column (`GET expr`).
+    pub fn get(mut self, expr: impl Into<Expr>) -> Self {
+        self.columns.push(Column::new(expr.into(), None));
+        self
+    }
+
+    /// Adds a column with an alias (`GET expr AS alias`).
+    pub fn get_as(mut self, expr: impl Into<Expr>, alias: &str) -> Self {
+        self.columns
+            .push(Column::new(expr.into(), Some(alias.to_string())));
+        self
+    }
+
+    /// `FOR LAST value unit`
+    pub fn for_last(mut self, value: u32, unit: TimeUnit) -> Self {
+        self.range = Some(TimeRange::Last(TimeSpec::new(value, unit)));
+        self
+    }
+
+    /// `FROM 'from' TO 'to'`
+    pub fn between(mut self, from: DateTime<Utc>, to: DateTime<Utc>) -> Self {
+        self.range = Some(TimeRange::Between(from, to));
+        self
+    }
+
+    /// `STEP value unit`
+    pub fn step(mut self, value: u32, unit: TimeUnit) -> Self {
+        self.step = Some(TimeSpec::new(value, unit));
+        self
+    }
+
+    /// `RESAMPLE value unit`
+    pub fn resample(mut self, value: u32, unit: TimeUnit) -> Self {
+        self.resample = Some(TimeSpec::new(value, unit));
+        self
+    }
+
+    /// `WHERE condition`, given as a boolean expression of comparisons combined
+    /// with `&` (AND), `|` (OR) and `!` (NOT).
+    pub fn filter(mut self, condition: Expr) -> Self {
+        self.filter = Some(condition);
+        self
+    }
+
+    /// `ORDER BY expr ASC|DESC`
+    pub fn order_by(mut self, expr: impl Into<Expr>, order: SortOrder) -> Self {
+        self.order_by = Some(OrderBy::new(SortKey::Expr(expr.into()), order));
+        self
+    }
+
+    /// `ORDER BY alias ASC|DESC`, the alias of one of the columns.
+    pub fn order_by_alias(mut self, alias: &str, order: SortOrder) -> Self {
+        self.order_by = Some(OrderBy::new(SortKey::Alias(alias.to_string()), order));
+        self
+    }
+
+    /// `LIMIT limit`
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// `OFFSET offset`, used together with [`limit`](Self::limit).
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// `SUMMARY`
+    pub fn summary(mut self) -> Self {
+        self.summary = true;
+        self
+    }
+
+    /// Builds the query, which must have a time range and step. Fails if validating
+    /// the query finds errors; warnings are ignored (see [`validate`]).
+    /// Only the well-known metrics are accepted, see [`build_with`](QueryBuilder::build_with).
+    pub fn build(self) -> Result<Query, BuildError> {
+        self.build_with(MetricCatalog::well_known())
+    }
+
+    /// Builds the query as [`build`](QueryBuilder::build), accepting the metrics
+    /// of the `catalog`.
+    pub fn build_with(self, catalog: &MetricCatalog) -> Result<Query, BuildError> {
+        let range = self.range.ok_or(BuildError::Missing("time range".into()))?;
+        let step = self.step.ok_or(BuildError::Missing("step".into()))?;
+        if let Some(filter) = self.filter.as_ref().filter(|filter| !filter.is_boolean()) {
+            return Err(BuildError::InvalidFilter(filter.to_string()));
+        }
+
+        let query = Query::new(self.columns, range, step)
+            .with_resample(self.resample)
+            .with_filter(self.filter)
+            .with_order_by(self.order_by)
+            .with_limit(self.limit, self.offset)
+            .with_summary(self.summary);
+
+        let issues = validate(&query, catalog);
+        if issues.iter().any(ValidationIssue::is_error) {
+            return Err(BuildError::Invalid(issues));
+        }
+        Ok(query)
+    }
+}
+
+/// Symbol whose metrics are selected, i.e. `sym("AAPL").close()` for `AAPL.close`.
+/// Any ticker may be given, i.e. `sym("BRK.B")`, which is quoted when written.
+pub fn sym(symbol: &str) -> Symbol {
+    Symbol {
+        symbol: symbol.to_string(),
+        exchange: None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    symbol: String,
+    exchange: Option<String>,
+}
+
+impl Symbol {
+    /// Qualifies the symbol by its exchange: `sym("AAPL").on("XNAS")` is `XNAS:AAPL`.
+    pub fn on(mut self, exchange: &str) -> Self {
+        self.exchange = Some(exchange.to_string());
+        self
+    }
+
+    pub fn metric(&self, metric: Metric) -> Expr {
+        let data = SymbolMetric::new(&self.symbol, metric);
+        Expr::Data(data.with_exchange(self.exchange.as_deref()))
+    }
+
+    pub fn volume(&self) -> Expr {
+        self.metric(Metric::VOLUME)
+    }
+
+    pub fn max(&self) -> Expr {
+        self.metric(Metric::MAX)
+    }
+
+    pub fn min(&self) -> Expr {
+        self.metric(Metric::MIN)
+    }
+
+    pub fn open(&self) -> Expr {
+        self.metric(Metric::OPEN)
+    }
+
+    pub fn close(&self) -> Expr {
+        self.metric(Metric::CLOSE)
+    }
+
+    pub fn avg(&self) -> Expr {
+        self.metric(Metric::AVG)
+    }
+}
+
+/// Function call, i.e. `call(Function::Sma, [sym("AAPL").close(), 20.into()])`.
+/// The arguments are checked when the query is built.
+pub fn call(func: Function, args: impl IntoIterator<Item = Expr>) -> Expr {
+    Expr::Call(func, args.into_iter().collect())
+}
+
+impl From<f64> for Expr {
+    fn from(value: f64) -> Self {
+        Expr::Value(value)
+    }
+}
+
+impl From<i32> for Expr {
+    fn from(value: i32) -> Self {
+        Expr::Value(value as f64)
+    }
+}
+
+impl From<SymbolMetric> for Expr {
+    fn from(data: SymbolMetric) -> Self {
+        Expr::Data(data)
+    }
+}
+
+/// Comparisons, giving boolean expressions usable as columns and as conditions.
+impl Expr {
+    pub fn gt(self, other: impl Into<Expr>) -> Expr {
+        self.compare(Comparison::Gt, other)
+    }
+
+    pub fn ge(self, other: impl Into<Expr>) -> Expr {
+        self.compare(Comparison::Ge, other)
+    }
+
+    pub fn lt(self, other: impl Into<Expr>) -> Expr {
+        self.compare(Comparison::Lt, other)
+    }
+
+    pub fn le(self, other: impl Into<Expr>) -> Expr {
+        self.compare(Comparison::Le, other)
+    }
+
+    pub fn equals(self, other: impl Into<Expr>) -> Expr {
+        self.compare(Comparison::Eq, other)
+    }
+
+    pub fn not_equals(self, other: impl Into<Expr>) -> Expr {
+        self.compare(Comparison::Ne, other)
+    }
+
+    fn compare(self, op: Comparison, other: impl Into<Expr>) -> Expr {
+        Expr::Binary(
+            Box::new(self),
+            Operator::Compare(op),
+            Box::new(other.into()),
+        )
+    }
+}
+
+/// Implements a binary operator of expressions, also with a number on the left.
+macro_rules! binary_operator {
+    ($trait:ident, $method:ident, $op:expr) => {
+        impl<T: Into<Expr>> $trait<T> for Expr {
+            type Output = Expr;
+
+            fn $method(self, other: T) -> Expr {
+                Expr::Binary(Box::new(self), $op, Box::new(other.into()))
+            }
+        }
+
+        impl $trait<Expr> for f64 {
+            type Output = Expr;
+
+            fn $method(self, other: Expr) -> Expr {
+                Expr::from(self).$method(other)
+            }
+        }
+
+        impl $trait<Expr> for i32 {
+            type Output = Expr;
+
+            fn $method(self, other: Expr) -> Expr {
+                Expr::from(self).$method(other)
+            }
+        }
+    };
+}
+
+binary_operator!(Add, add, Operator::Add);
+binary_operator!(Sub, sub, Operator::Sub);
+binary_operator!(Mul, mul, Operator::Mul);
+binary_operator!(Div, div, Operator::Div);
+
+/// `a & b` is `a AND b`
+impl BitAnd for Expr {
+    type Output = Expr;
+
+    fn bitand(self, other: Expr) -> Expr {
+        Expr::Binary(Box::new(self), Operator::And, Box::new(other))
+    }
+}
+
+/// `a | b` is `a OR b`
+impl BitOr for Expr {
+    type Output = Expr;
+
+    fn bitor(self, other: Expr) -> Expr {
+        Expr::Binary(Box::new(self), Operator::Or, Box::new(other))
+    }
+}
+
+/// `!a` is `NOT a`
+impl Not for Expr {
+    type Output = Expr;
+
+    fn not(self) -> Expr {
+        Expr::Not(Box::new(self))
+    }
+}
+
+/// Negative literals are folded into a single value, as by the parser: `-3` is `Value(-3)`.
+impl Neg for Expr {
+    type Output = Expr;
+
+    fn neg(self) -> Expr {
+        match self {
+            Expr::Value(val) => Expr::Value(-val),
+            other => Expr::Neg(Box::new(other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Function::*, TimeUnit::*, parse_query};
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_builder_matches_parser() {
+        let aapl = sym("AAPL");
+        let query = Query::builder()
+            .get(-aapl.close() * 2 - 3 / aapl.open() + -1)
+            .get_as(call(Max, [aapl.max(), sym("MSFT").max()]), "max")
+            .get((aapl.close().gt(1) | aapl.open().le(2)) & !aapl.avg().equals(0))
+            .between(
+                Utc.with_ymd_and_hms(2025, 6, 1, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2025, 6, 2, 0, 0, 0).unwrap(),
+            )
+            .step(1, Hour)
+            .resample(15, Minute)
+            .filter(aapl.volume().ge(10) | aapl.volume().not_equals(5) & !aapl.close().lt(1.5))
+            .order_by(call(Sma, [aapl.close(), 5.into()]), SortOrder::Asc)
+            .limit(10)
+            .offset(2)
+            .summary()
+            .build()
+            .unwrap();
+
+        let src = "GET -AAPL.close * 2 - 3 / AAPL.open + -1, MAX(AAPL.max, MSFT.max) AS max, \
+                   (AAPL.close > 1 OR AAPL.open <= 2) AND NOT AAPL.avg = 0 \
+                   FROM '2025-06-01T00:00:00Z' TO '2025-06-02T00:00:00Z' STEP 1 hour \
+                   RESAMPLE 15 minutes WHERE AAPL.volume >= 10 OR AAPL.volume != 5 AND \
+                   NOT AAPL.close < 1.5 ORDER BY SMA(AAPL.close, 5) ASC LIMIT 10 OFFSET 2 SUMMARY";
+        assert_eq!(parse_query(src).unwrap(), query);
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let close = || sym("AAPL").close();
+        assert_eq!(
+            Err(BuildError::Missing("step".into())),
+            Query::builder().get(close()).for_last(1, Day).build()
+        );
+        assert_eq!(
+            "Filter AAPL.close + 1 is not a condition",
+            Query::builder()
+                .get(close())
+                .for_last(1, Day)
+                .step(1, Hour)
+                .filter(close() + 1)
+                .build()
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "Invalid query: ORDER BY refers to unknown column alias x; Invalid call \
+             SMA(AAPL.close, 0): the period must be an integer from 1 to 100000",
+            Query::builder()
+                .get(call(Sma, [close(), 0.into()]))
+                .for_last(1, Day)
+                .step(1, Hour)
+                .order_by_alias("x", SortOrder::Desc)
+                .build()
+                .unwrap_err()
+                .to_string()
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use std::ops::Range;
+
+use pest::{Parser, iterators::Pair};
+
+use super::{
+    builders::{build_clause, build_column, build_step_clause, build_time_range},
+    error::ParseError,
+    model::{Column, Expr, OrderBy, Query, SortKey, TimeRange, TimeSpec},
+    parser::{QueryParser, Rule},
+};
+
+type ParseResult<T> = Result<T, ParseError>;
+
+/// Keywords starting the parts of a query, with the rule of the part
+/// (the column list following `GET` is parsed column by column).
+const KEYWORDS: &[(&str, Rule)] = &[
+    ("GET", Rule::expr_list),
+    ("FOR LAST", Rule::time_range),
+    ("FROM", Rule::time_range),
+    ("STEP", Rule::step_clause),
+    ("RESAMPLE", Rule::resample_clause),
+    ("WHERE", Rule::where_clause),
+    ("ORDER BY", Rule::order_clause),
+    ("LIMIT", Rule::limit_clause),
+    ("SUMMARY", Rule::summary_clause),
+];
+
+/// Parts of a query in the order they must come in; the first `REQUIRED` are mandatory.
+const PARTS: &[Rule] = &[
+    Rule::expr_list,
+    Rule::time_range,
+    Rule::step_clause,
+    Rule::resample_clause,
+    Rule::where_clause,
+    Rule::order_clause,
+    Rule::limit_clause,
+    Rule::summary_clause,
+];
+const REQUIRED: usize = 3;
+
+/// Parses a query which may have errors. The source is split into parts at top-level
+/// clause keywords and the column list at commas; each is parsed on its own, so an error
+/// in one does not hide the errors in the others. Returns the query built from the parts
+/// which parsed (if the required ones did) and the errors, in the order of the source.
+///
+/// Optional clauses are checked beyond their syntax only if the query could be built,
+/// as they depend on its columns and step.
+pub(crate) fn recover_query(src: &str) -> (Option<Query>, Vec<ParseError>) {
+    let mut errors = Vec::new();
+    let mut columns: Option<Vec<Column>> = None;
+    let mut range: Option<TimeRange> = None;
+    let mut step: Option<TimeSpec> = None;
+    let mut clauses: Vec<(usize, Pair<Rule>)> = Vec::new();
+
+    // index in PARTS of the first part which may come next
+    let mut next = 0;
+
+    for (span, rule) in split_parts(src) {
+        let part = &src[span.clone()];
+        let Some(rule) = rule else {
+            // text before the first keyword
+            if let Some(pos) = part.find(|c: char| !c.is_whitespace()) {
+                errors.push(ParseError::unexpected(span.start + pos, vec!["GET".into()]));
+            }
+            continue;
+        };
+
+        let Some(index) = PARTS.iter().position(|r| *r == rule) else {
+            continue;
+        };
+        if index < next {
+            let expected = PARTS[next..]
+                .iter()
+                .flat_map(|r| part_keywords(*r))
+                .collect();
+            errors.push(ParseError::unexpected(span.start, expected));
+            continue;
+        }
+        if let Some(missing) = (next..index).find(|i| *i < REQUIRED) {
+            errors.push(ParseError::unexpected(
+                span.start,
+                part_keywords(PARTS[missing]),
+            ));
+        }
+        next = index + 1;
+
+        if rule == Rule::expr_list {
+            let list = span.start + "GET".len()..span.end;
+            columns = Some(recover_columns(src, list, &mut errors));
+            continue;
+        }
+        let pair = match QueryParser::parse(Rule::single_clause, part) {
+            Ok(mut pairs) => pairs.next(),
+            Err(err) => {
+                errors.push(ParseError::from(err).shifted(span.start));
+                continue;
+            }
+        };
+        let result = match rule {
+            Rule::time_range => build_time_range(pair).map(|r| range = Some(r)),
+            Rule::step_clause => build_step_clause(pair).map(|s| step = Some(s)),
+            _ => {
+                clauses.extend(pair.map(|pair| (span.start, pair)));
+                Ok(())
+            }
+        };
+        if let Err(err) = result {
+            errors.push(err.shifted(span.start));
+        }
+    }
+    if next < REQUIRED {
+        errors.push(ParseError::unexpected(
+            src.len(),
+            part_keywords(PARTS[next]),
+        ));
+    }
+
+    let query = match (columns, range, step) {
+        (Some(columns), Some(range), Some(step)) if !columns.is_empty() => {
+            let mut query = Query::new(columns, range, step);
+            for (offset, pair) in clauses {
+                let rule = pair.as_rule();
+                match build_clause(pair, query.clone()) {
+                    Ok(with_clause) => query = shifted_clause(with_clause, rule, offset),
+                    Err(err) => errors.push(err.shifted(offset)),
+                }
+            }
+            Some(query)
+        }
+        _ => None,
+    };
+
+    errors.sort_by_key(|err| err.span().map(|span| span.start));
+    (query, errors)
+}
+
+/// Parses the comma-separated columns in `list`, skipping those with errors.
+fn recover_columns(src: &str, list: Range<usize>, errors: &mut Vec<ParseError>) -> Vec<Column> {
+    let mut bounds = vec![list.start];
+    bounds.extend(
+        top_level(&src[list.clone()])
+            .into_iter()
+            .filter(|(_, c)| *c == ',')
+            .map(|(pos, _)| list.start + pos + 1),
+    );
+    bounds.push(list.end + 1);
+
+    bounds
+        .windows(2)
+        .filter_map(|bounds| {
+            let (start, end) = (bounds[0], bounds[1] - 1);
+            match parse_column(&src[start..end]) {
+                Ok(column) => {
+                    let alias = column.alias().map(str::to_string);
+                    Some(Column::new(shifted(column.expr(), start), alias))
+                }
+                Err(err) => {
+                    errors.push(err.shifted(start));
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Moves the spans of the data in the clause built from a `rule` part of the source
+/// starting at `offset`, as for errors.
+fn shifted_clause(query: Query, rule: Rule, offset: usize) -> Query {
+    match (rule, query.order_by()) {
+        (Rule::where_clause, _) => {
+            let filter = query.filter().map(|filter| shifted(filter, offset));
+            query.with_filter(filter)
+        }
+        (Rule::order_clause, Some(order_by)) if let SortKey::Expr(expr) = order_by.key() => {
+            let order_by = OrderBy::new(SortKey::Expr(shifted(expr, offset)), order_by.order());
+            query.with_order_by(Some(order_by))
+        }
+        _ => query,
+    }
+}
+
+fn shifted(expr: &Expr, offset: usize) -> Expr {
+    expr.map_data(&mut |sm| {
+        let span = sm.span().map(|span| span.start + offset..span.end + offset);
+        sm.clone().with_span(span)
+    })
+}
+
+fn parse_column(src: &str) -> ParseResult<Column> {
+    QueryParser::parse(Rule::single_column, src)
+        .map_err(ParseError::from)
+        .and_then(|mut pairs| build_column(pairs.next()))
+}
+
+/// Splits the source at the clause keywords outside parentheses, braces, quotes and comments.
+/// Each part has the rule it is parsed with; the text before the first keyword has none.
+fn split_parts(src: &str) -> Vec<(Range<usize>, Option<Rule>)> {
+    let mut parts = vec![(0..src.len(), None)];
+    for (pos, _) in top_level(src) {
+        if src[..pos].ends_with(|c: char| is_name_char(c) || c == '.') {
+            continue;
+        }
+        let keyword = KEYWORDS
+            .iter()
+            .find(|(keyword, _)| starts_with_keyword(&src[pos..], keyword));
+        if let Some((_, rule)) = keyword {
+            if let Some((last, _)) = parts.last_mut() {
+                last.end = pos;
+            }
+            parts.push((pos..src.len(), Some(*rule)));
+        }
+    }
+    parts
+}
+
+/// Whether the text starts with the keyword, in any case and with any whitespace
+/// between its words (i.e. `order  by` for `ORDER BY`).
+fn starts_with_keyword(text: &str, keyword: &str) -> bool {
+    let mut rest = text;
+    for (i, word) in keyword.split(' ').enumerate() {
+        if i > 0 {
+            rest = rest.trim_start();
+        }
+        match rest.get(..word.len()) {
+            Some(prefix) if prefix.eq_ignore_ascii_case(word) => rest = &rest[word.len()..],
+            _ => return false,
+        }
+    }
+    !rest.starts_with(|c: char| is_name_char(c) || c == '.')
+}
+
+/// Characters outside parentheses, braces (symbol sets), quotes (timestamps and symbols)
+/// and comments, with their byte positions.
+fn top_level(src: &str) -> Vec<(usize, char)> {
+    let mut chars = Vec::new();
+    let mut depth = 0usize;
+    let mut pos = 0;
+    while let Some(c) = src[pos..].chars().next() {
+        let rest = &src[pos..];
+        pos += if c == '\'' || c == '"' {
+            rest[1..].find(c).map_or(rest.len(), |len| len + 2)
+        } else if rest.starts_with("--") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            comment.find("*/").map_or(rest.len(), |len| len + 4)
+        } else {
+            match c {
+                '(' | '{' => depth += 1,
+                ')' | '}' => depth = depth.saturating_sub(1),
+                _ if depth == 0 => chars.push((pos, c)),
+                _ => {}
+            }
+            c.len_utf8()
+        };
+    }
+    chars
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Keywords a part of the query starts with, as listed among expected tokens.
+fn part_keywords(rule: Rule) -> Vec<String> {
+    KEYWORDS
+        .iter()
+        .filter(|(_, r)| *r == rule)
+        .map(|(keyword, _)| keyword.to_string())
+        .collect()
+}
@@ -1,43 +1,136 @@
-use pest::{Parser, iterators::Pairs};
+use pest::{Parser, iterators::Pair};
 
 use super::{
     ParseError,
-    builders::{build_expr, build_for_clause, build_query, build_step_clause},
+    builders::{
+        build_condition, build_expr, build_for_clause, build_query, build_step_clause,
+        build_time_range, reject_symbol_sets,
+    },
+    catalog::MetricCatalog,
     model::*,
     parser::{QueryParser, Rule},
+    recovery::recover_query,
+    validate::unknown_metrics,
 };
 
 type ParseResult<T> = Result<T, ParseError>;
 
 /// Parses a full query string into a Query struct.
 pub fn parse_query(src: &str) -> ParseResult<Query> {
-    build_query(parse(src, Rule::query)?.next())
+    parse_with(src, Rule::query, build_query)
+}
+
+/// Parses a query which may have errors, reporting all of them rather than just the first.
+/// Parts of the query with errors are left out: columns of the GET list and optional clauses
+/// are skipped; if the GET list, time range or step can't be parsed, there is no query.
+/// Metrics are checked against the `catalog`.
+pub fn parse_query_recovering(src: &str, catalog: &MetricCatalog) -> PartialQuery {
+    let (query, mut diagnostics) = match parse_query(src) {
+        Ok(query) => (Some(query), Vec::new()),
+        Err(err) => {
+            let (query, diagnostics) = recover_query(src);
+            match diagnostics.is_empty() {
+                // the query is invalid, even if no part on its own is
+                true => (query, vec![err]),
+                false => (query, diagnostics),
+            }
+        }
+    };
+    if let Some(query) = &query {
+        for sm in unknown_metrics(query, catalog) {
+            let name = sm.metric().name();
+            let err = ParseError::unknown(
+                "metric".into(),
+                name.to_string().into(),
+                catalog.suggest(name),
+            );
+            diagnostics.push(err.within(sm.span()));
+        }
+        diagnostics.sort_by_key(|err| err.span().map(|span| span.start));
+    }
+    PartialQuery {
+        query,
+        diagnostics: diagnostics
+            .into_iter()
+            .map(|err| err.with_source(src))
+            .collect(),
+    }
+}
+
+/// Query parsed by `parse_query_recovering`, with the errors found.
+#[derive(Debug)]
+pub struct PartialQuery {
+    query: Option<Query>,
+    diagnostics: Vec<ParseError>,
+}
+
+impl PartialQuery {
+    /// The query without the parts which have errors, if its required parts parsed.
+    pub fn query(&self) -> Option<&Query> {
+        self.query.as_ref()
+    }
+
+    /// Errors found, in the order of the source.
+    pub fn diagnostics(&self) -> &[ParseError] {
+        &self.diagnostics
+    }
+
+    pub fn into_parts(self) -> (Option<Query>, Vec<ParseError>) {
+        (self.query, self.diagnostics)
+    }
 }
 
 /// Parses a single expression into an Expr struct.
 pub fn parse_expr(src: &str) -> ParseResult<Expr> {
-    build_expr(parse(src, Rule::expr)?.next())
+    parse_with(src, Rule::expr, |pair| {
+        build_expr(reject_symbol_sets(pair)?)
+    })
+}
+
+/// Parses a condition (as in the WHERE clause): an expression yielding a boolean.
+pub fn parse_condition(src: &str) -> ParseResult<Expr> {
+    parse_with(src, Rule::expr, |pair| {
+        build_condition(reject_symbol_sets(pair)?)
+    })
 }
 
 /// Parses a "FOR LAST" time clause into a TimeSpec struct.
 pub fn parse_for_clause(src: &str) -> ParseResult<TimeSpec> {
-    build_for_clause(parse(src, Rule::for_clause)?.next())
+    parse_with(src, Rule::for_clause, build_for_clause)
+}
+
+/// Parses a time range ("FOR LAST ..." or "FROM ... TO ...") into a TimeRange.
+pub fn parse_time_range(src: &str) -> ParseResult<TimeRange> {
+    parse_with(src, Rule::time_range, build_time_range)
 }
 
 /// Parses a "STEP" clause into a TimeSpec struct.
 pub fn parse_step_clause(src: &str) -> ParseResult<TimeSpec> {
-    build_step_clause(parse(src, Rule::step_clause)?.next())
+    parse_with(src, Rule::step_clause, build_step_clause)
 }
 
-/// Internal helper to parse the source string using the given rule.
-fn parse(input: &str, rule: Rule) -> ParseResult<Pairs<Rule>> {
-    Ok(QueryParser::parse(rule, input)?)
+/// Internal helper to parse the source string using the given rule and build the result.
+/// Errors are resolved against the source (line, column and snippet).
+fn parse_with<T>(
+    src: &str,
+    rule: Rule,
+    build: fn(Option<Pair<Rule>>) -> ParseResult<T>,
+) -> ParseResult<T> {
+    QueryParser::parse(rule, src)
+        .map_err(ParseError::from)
+        .and_then(|mut pairs| build(pairs.next()))
+        .map_err(|err| err.with_source(src))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use pest::iterators::Pair;
+    use crate::{ErrorKind, MetricInfo};
+    use chrono::Utc;
+
+    fn error_kind<T>(result: ParseResult<T>) -> Option<ErrorKind> {
+        result.err().map(|err| err.kind().clone())
+    }
 
     /// Helper function useful for debugging a pair with all it inners
     #[allow(dead_code)]
@@ -58,14 +151,104 @@ mod test {
     fn test_value_expr_parse() {
         let input = r"32";
         let expr = parse_expr(input).unwrap();
-        assert_eq!(Expr::Value(32), expr);
+        assert_eq!(Expr::Value(32.0), expr);
+    }
+
+    #[test]
+    fn test_decimal_expr_parse() {
+        assert_eq!(Expr::Value(1.05), parse_expr(r"1.05").unwrap());
+        assert_eq!(Expr::Value(2.5e-3), parse_expr(r"2.5e-3").unwrap());
+        assert_eq!(Expr::Value(1E6), parse_expr(r"1E6").unwrap());
+        assert!(parse_query(r"GET 1. FOR LAST 1 day STEP 1 hour").is_err());
+
+        let err = parse_query(r"GET AAPL.close * 1e400 FOR LAST 1 day STEP 1 hour").unwrap_err();
+        assert_eq!(
+            &ErrorKind::InvalidValue("1e400".into(), "number".into()),
+            err.kind()
+        );
+        assert_eq!(Some(17..22), err.span());
+        assert_eq!(Expr::Value(1e300), parse_expr(r"1e300").unwrap());
+    }
+
+    #[test]
+    fn test_unary_minus_parse() {
+        let close = || Box::new(Expr::Data(SymbolMetric::new("AAPL", Metric::CLOSE)));
+        assert_eq!(
+            Expr::Binary(close(), Operator::Sub, Box::new(Expr::Value(-3.0))),
+            parse_expr(r"AAPL.close - -3").unwrap()
+        );
+        assert_eq!(Expr::Neg(close()), parse_expr(r"-AAPL.close").unwrap());
+        assert_eq!(
+            Expr::Binary(
+                Box::new(Expr::Neg(Box::new(Expr::Binary(
+                    close(),
+                    Operator::Sub,
+                    Box::new(Expr::Value(1.0))
+                )))),
+                Operator::Mul,
+                Box::new(Expr::Value(2.0))
+            ),
+            parse_expr(r"-(AAPL.close - 1) * 2").unwrap()
+        );
     }
 
     #[test]
     fn test_symbol_expr_parse() {
         let input = r"AAPL.max";
         let expr = parse_expr(input).unwrap();
-        assert_eq!(Expr::Data(SymbolMetric::new("AAPL", Metric::Max)), expr);
+        assert_eq!(Expr::Data(SymbolMetric::new("AAPL", Metric::MAX)), expr);
+
+        let expr = parse_expr(r"AAPL.open_interest").unwrap();
+        let open_interest = SymbolMetric::new("AAPL", Metric::new("open_interest"));
+        assert_eq!(Expr::Data(open_interest), expr);
+        assert!(parse_expr(r"AAPL.1st").is_err());
+    }
+
+    #[test]
+    fn test_quoted_and_qualified_symbols() {
+        let brk = SymbolMetric::new("BRK.B", Metric::CLOSE);
+        assert_eq!(
+            Expr::Data(brk.clone()),
+            parse_expr(r#""BRK.B".close"#).unwrap()
+        );
+        assert_eq!(
+            Expr::Data(SymbolMetric::new("AAPL", Metric::OPEN)),
+            parse_expr(r#""AAPL".open"#).unwrap()
+        );
+
+        let aapl = SymbolMetric::new("AAPL", Metric::MAX).with_exchange(Some("XNAS"));
+        assert_eq!(
+            Expr::Data(aapl.clone()),
+            parse_expr("XNAS:AAPL.max").unwrap()
+        );
+        assert_eq!("XNAS:AAPL", aapl.qualified_symbol());
+
+        let rds = SymbolMetric::new("RDS-A", Metric::VOLUME).with_exchange(Some("XNYS"));
+        let expr = parse_expr(r#"XNYS:"RDS-A".volume / "BRK.B".close"#).unwrap();
+        assert_eq!(
+            Expr::Binary(
+                Box::new(Expr::Data(rds)),
+                Operator::Div,
+                Box::new(Expr::Data(brk))
+            ),
+            expr
+        );
+        assert_eq!(r#"XNYS:"RDS-A".volume / "BRK.B".close"#, expr.to_string());
+
+        // keywords and comment markers within quotes are part of the symbol
+        let query = parse_query(r#"GET "FROM--X".close FOR LAST 1 day STEP 1 hour"#).unwrap();
+        let columns = query.columns();
+        assert_eq!(r#""FROM--X".close"#, columns[0].to_string());
+
+        assert!(parse_expr(r#""BRK B".close"#).is_err());
+        assert!(parse_expr(r#"XNAS :AAPL.close"#).is_err());
+        assert!(parse_expr(r#""XNAS:AAPL".close"#).is_err());
+        assert!(parse_expr(r#"XNAS:"XNAS:AAPL".close"#).is_err());
+        let err = parse_query_recovering(
+            r#"GET "FROM".close FOR LAST 1 days STEP 1 hourz"#,
+            MetricCatalog::well_known(),
+        );
+        assert_eq!(1, err.diagnostics().len());
     }
 
     #[test]
@@ -74,20 +257,619 @@ mod test {
         let expr = parse_expr(input).unwrap();
         assert_eq!(
             Expr::Binary(
-                Box::new(Expr::Data(SymbolMetric::new("AAPL", Metric::Volume))),
+                Box::new(Expr::Data(SymbolMetric::new("AAPL", Metric::VOLUME))),
                 Operator::Div,
-                Box::new(Expr::Value(1000))
+                Box::new(Expr::Value(1000.0))
             ),
             expr
         );
     }
 
+    #[test]
+    fn test_function_call_parse() {
+        let close = Expr::Data(SymbolMetric::new("AAPL", Metric::CLOSE));
+        assert_eq!(
+            Expr::Call(Function::Sqrt, vec![close.clone()]),
+            parse_expr(r"sqrt(AAPL.close)").unwrap()
+        );
+        assert_eq!(
+            Expr::Binary(
+                Box::new(Expr::Call(
+                    Function::Pow,
+                    vec![close.clone(), Expr::Value(2.0)]
+                )),
+                Operator::Add,
+                Box::new(Expr::Value(1.0))
+            ),
+            parse_expr(r"POW(AAPL.close, 2) + 1").unwrap()
+        );
+        assert_eq!(
+            Expr::Call(
+                Function::Max,
+                vec![close, Expr::Data(SymbolMetric::new("MSFT", Metric::CLOSE))]
+            ),
+            parse_expr(r"max(AAPL.close, MSFT.close)").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_function_call_errors() {
+        assert!(matches!(
+            error_kind(parse_expr(r"foo(AAPL.close)")),
+            Some(ErrorKind::UnknownFunction(name)) if name == "foo"
+        ));
+        assert!(matches!(
+            error_kind(parse_expr(r"pow(AAPL.close)")),
+            Some(ErrorKind::InvalidArity(_, 2, 1))
+        ));
+        assert!(matches!(
+            error_kind(parse_expr(r"abs()")),
+            Some(ErrorKind::InvalidArity(_, 1, 0))
+        ));
+    }
+
+    #[test]
+    fn test_window_function_parse() {
+        let expr = parse_expr(r"SMA(AAPL.close, 5) - EMA(AAPL.close, 12)").unwrap();
+        assert_eq!(11, expr.lookback());
+
+        let expr = parse_expr(r"rolling_max(STDDEV(AAPL.close, 20), 3)").unwrap();
+        assert_eq!(21, expr.lookback());
+        assert_eq!("ROLLING_MAX(STDDEV(AAPL.close, 20), 3)", expr.to_string());
+
+        assert_eq!(0, parse_expr(r"AAPL.close * 2").unwrap().lookback());
+    }
+
+    #[test]
+    fn test_series_function_parse() {
+        assert_eq!(1, parse_expr(r"DIFF(AAPL.close)").unwrap().lookback());
+        assert_eq!(3, parse_expr(r"LAG(AAPL.close, 3)").unwrap().lookback());
+        assert_eq!(0, parse_expr(r"LEAD(AAPL.close, 3)").unwrap().lookback());
+
+        let expr = parse_expr(r"cumprod(1 + pct_change(AAPL.close))").unwrap();
+        assert_eq!(1, expr.lookback());
+        assert_eq!("CUMPROD(1 + PCT_CHANGE(AAPL.close))", expr.to_string());
+
+        assert!(matches!(
+            error_kind(parse_expr(r"LAG(AAPL.close)")),
+            Some(ErrorKind::InvalidArity(_, 2, 1))
+        ));
+    }
+
+    #[test]
+    fn test_aggregate_function_parse() {
+        assert!(parse_expr(r"AVG(AAPL.close)").unwrap().is_aggregate());
+        assert!(
+            parse_expr(r"MAX(AAPL.close) - MIN(AAPL.close)")
+                .unwrap()
+                .is_aggregate()
+        );
+        assert!(
+            parse_expr(r"PERCENTILE(AAPL.volume, 95) / 2")
+                .unwrap()
+                .is_aggregate()
+        );
+        assert!(
+            !parse_expr(r"MAX(AAPL.close, AAPL.open)")
+                .unwrap()
+                .is_aggregate()
+        );
+        assert!(
+            !parse_expr(r"AAPL.close - AVG(AAPL.close)")
+                .unwrap()
+                .is_aggregate()
+        );
+        assert!(!parse_expr(r"10").unwrap().is_aggregate());
+
+        assert!(matches!(
+            error_kind(parse_expr(r"MIN(AAPL.close, AAPL.open, AAPL.max)")),
+            Some(ErrorKind::InvalidArity(_, 2, 3))
+        ));
+        assert!(matches!(
+            error_kind(parse_expr(r"PERCENTILE(AAPL.close, 101)")),
+            Some(ErrorKind::InvalidValue(_, rule)) if rule == "percentile"
+        ));
+    }
+
+    #[test]
+    fn test_summary_clause() {
+        let input = r"GET AAPL.close FOR LAST 1 day STEP 1 hour SUMMARY";
+        let query = parse_query(input).unwrap();
+        assert!(query.summary());
+        assert_eq!(input, query.to_string());
+
+        assert!(
+            !parse_query(r"GET AAPL.close FOR LAST 1 day STEP 1 hour")
+                .unwrap()
+                .summary()
+        );
+        assert!(parse_query(r"GET AAPL.close FOR LAST 1 day STEP 1 hour SUMARY").is_err());
+    }
+
+    #[test]
+    fn test_resample_clause() {
+        let query = parse_query(r"GET AAPL.close FOR LAST 7 days STEP 1 day RESAMPLE").unwrap();
+        assert_eq!(Some(&TimeSpec::new(1, TimeUnit::Hour)), query.resample());
+        assert_eq!(Some(24), query.resample_factor());
+
+        let input = r"GET AAPL.close FOR LAST 1 day STEP 1 hour RESAMPLE 15 minutes SUMMARY";
+        let query = parse_query(input).unwrap();
+        assert_eq!(Some(4), query.resample_factor());
+        assert!(query.summary());
+        assert_eq!(input, query.to_string());
+
+        assert_eq!(
+            None,
+            parse_query(r"GET AAPL.close FOR LAST 1 day STEP 1 hour")
+                .unwrap()
+                .resample()
+        );
+        for input in [
+            r"GET AAPL.close FOR LAST 1 day STEP 1 hour RESAMPLE 7 minutes",
+            r"GET AAPL.close FOR LAST 1 day STEP 1 hour RESAMPLE 1 hour",
+            r"GET AAPL.close FOR LAST 1 day STEP 1 second RESAMPLE",
+        ] {
+            assert!(matches!(
+                error_kind(parse_query(input)),
+                Some(ErrorKind::InvalidValue(_, rule)) if rule == "resample_clause"
+            ));
+        }
+    }
+
+    #[test]
+    fn test_condition_parse() {
+        let data = |metric| Expr::Data(SymbolMetric::new("AAPL", metric));
+        let volume = || data(Metric::VOLUME).gt(2000);
+        let rising = || data(Metric::CLOSE).gt(data(Metric::OPEN));
+
+        assert_eq!(
+            volume() & rising(),
+            parse_condition(r"AAPL.volume > 2000 AND AAPL.close > AAPL.open").unwrap()
+        );
+        assert_eq!(
+            volume() | !rising() & volume(),
+            parse_condition(
+                r"AAPL.volume > 2000 OR NOT AAPL.close > AAPL.open AND AAPL.volume > 2000"
+            )
+            .unwrap()
+        );
+
+        let cond = parse_condition(
+            r"(AAPL.close - 1 >= 2 OR AAPL.close <> 3) AND NOT (AAPL.close = 1 AND AAPL.open <= 2)",
+        )
+        .unwrap();
+        assert_eq!(
+            "(AAPL.close - 1 >= 2 OR AAPL.close != 3) AND NOT (AAPL.close = 1 AND AAPL.open <= 2)",
+            cond.to_string()
+        );
+        assert_eq!(
+            4,
+            parse_condition(r"SMA(AAPL.close, 5) < AAPL.close")
+                .unwrap()
+                .lookback()
+        );
+        assert!(matches!(
+            error_kind(parse_condition(r"AAPL.close")),
+            Some(ErrorKind::InvalidValue(_, rule)) if rule == "condition"
+        ));
+    }
+
+    #[test]
+    fn test_where_clause() {
+        let input = r"GET AAPL.close, AAPL.volume FOR LAST 5 days STEP 1 hour WHERE AAPL.volume > 2000 AND AAPL.close > AAPL.open SUMMARY";
+        let query = parse_query(input).unwrap();
+        assert!(matches!(
+            query.filter(),
+            Some(Expr::Binary(_, Operator::And, _))
+        ));
+        assert!(query.summary());
+        assert_eq!(input, query.to_string());
+
+        assert!(
+            parse_query(r"GET AAPL.close FOR LAST 1 day STEP 1 hour")
+                .unwrap()
+                .filter()
+                .is_none()
+        );
+        assert!(parse_query(r"GET AAPL.close FOR LAST 1 day STEP 1 hour WHERE").is_err());
+        assert!(
+            parse_query(r"GET AAPL.close FOR LAST 1 day STEP 1 hour WHERE AAPL.close").is_err()
+        );
+    }
+
+    #[test]
+    fn test_order_and_limit_clauses() {
+        let input = r"GET AAPL.close, AAPL.volume AS vol FOR LAST 5 days STEP 1 hour ORDER BY vol DESC LIMIT 10 OFFSET 5";
+        let query = parse_query(input).unwrap();
+        let order_by = query.order_by().unwrap();
+        assert_eq!(&SortKey::Alias("vol".into()), order_by.key());
+        assert_eq!(SortOrder::Desc, order_by.order());
+        assert_eq!(Some(10), query.limit());
+        assert_eq!(5, query.offset());
+        assert_eq!(input, query.to_string());
+
+        let query = parse_query(
+            r"GET AAPL.close FOR LAST 1 day STEP 1 hour WHERE AAPL.close > 1 ORDER BY AAPL.max - AAPL.min ASC LIMIT 3",
+        )
+        .unwrap();
+        let order_by = query.order_by().unwrap();
+        assert_eq!(SortOrder::Asc, order_by.order());
+        assert_eq!("AAPL.max - AAPL.min", order_by.expr().unwrap().to_string());
+        assert_eq!((Some(3), 0), (query.limit(), query.offset()));
+
+        assert!(matches!(
+            error_kind(parse_query(r"GET AAPL.close FOR LAST 1 day STEP 1 hour ORDER BY vol")),
+            Some(ErrorKind::InvalidValue(_, rule)) if rule == "sort_key"
+        ));
+        assert!(parse_query(r"GET AAPL.close FOR LAST 1 day STEP 1 hour OFFSET 5").is_err());
+        assert!(parse_query(r"GET AAPL.close AS c FOR LAST 1 day STEP 1 hour ORDERBY c").is_err());
+        assert!(parse_query(r"GET AAPL.close AS c FOR LAST 1 day STEP 1 hour ORDER BYc").is_err());
+    }
+
+    #[test]
+    fn test_conditional_expr_parse() {
+        let close = || Box::new(Expr::Data(SymbolMetric::new("AAPL", Metric::CLOSE)));
+        let sma = Expr::Call(Function::Sma, vec![*close(), Expr::Value(20.0)]);
+        let above = Expr::Binary(close(), Operator::Compare(Comparison::Gt), Box::new(sma));
+
+        let expr = parse_expr(r"IF(AAPL.close > SMA(AAPL.close, 20), 1, 0)").unwrap();
+        assert_eq!(
+            Expr::Case(
+                vec![(above.clone(), Expr::Value(1.0))],
+                Some(Box::new(Expr::Value(0.0)))
+            ),
+            expr
+        );
+        assert_eq!(19, expr.lookback());
+        assert!(!expr.is_boolean());
+        assert!(above.is_boolean());
+
+        let expr = parse_expr(
+            r"CASE WHEN AAPL.close > 10 AND NOT AAPL.open < 5 THEN 1 WHEN AAPL.close >= 5 + 1 OR AAPL.close = 0 THEN 2 END",
+        )
+        .unwrap();
+        let Expr::Case(branches, None) = &expr else {
+            panic!("expected CASE without ELSE, got {expr:?}")
+        };
+        assert!(matches!(branches[0].0, Expr::Binary(_, Operator::And, _)));
+        assert!(matches!(branches[1].0, Expr::Binary(_, Operator::Or, _)));
+        assert_eq!(
+            "CASE WHEN AAPL.close > 10 AND NOT AAPL.open < 5 THEN 1 WHEN AAPL.close >= 5 + 1 OR AAPL.close = 0 THEN 2 END",
+            expr.to_string()
+        );
+
+        assert_eq!(
+            Expr::Not(Box::new(Expr::Data(SymbolMetric::new(
+                "NOTE",
+                Metric::CLOSE
+            )))),
+            parse_expr(r"NOT NOTE.close").unwrap()
+        );
+        assert!(matches!(
+            error_kind(parse_expr(r"IF(AAPL.close > 1, 1)")),
+            Some(ErrorKind::InvalidArity(_, 3, 2))
+        ));
+        assert!(parse_expr(r"CASE ELSE 1 END").is_err());
+    }
+
+    #[test]
+    fn test_error_position() {
+        let err = parse_query("GET AAPL.close,\n  foo(AAPL.open) FOR LAST 1 day STEP 1 hour")
+            .unwrap_err();
+        assert_eq!(&ErrorKind::UnknownFunction("foo".into()), err.kind());
+        assert_eq!(Some(18..21), err.span());
+        assert_eq!(Some((2, 3)), err.line_col());
+        assert_eq!(Some("foo"), err.token());
+        assert_eq!(
+            Some(" --> 2:3\n  |\n2 |   foo(AAPL.open) FOR LAST 1 day STEP 1 hour\n  |   ^^^"),
+            err.snippet()
+        );
+        assert_eq!("Unknown function: foo at line 2, column 3", err.to_string());
+
+        let err = parse_query(r"GET AAPL.close FOR LAST 1 day STEP 1 hour LIMIT x").unwrap_err();
+        assert_eq!(&ErrorKind::Syntax, err.kind());
+        assert_eq!(Some((1, 49)), err.line_col());
+        assert_eq!(Some("x"), err.token());
+        assert_eq!(&["whole number".to_string()], err.expected());
+
+        let err = parse_query(r"GET AAPL.close FOR LAST 1 day").unwrap_err();
+        assert_eq!(Some("end of input"), err.token());
+        assert_eq!(
+            "Syntax error: unexpected end of input, expected STEP at line 1, column 30",
+            err.to_string()
+        );
+
+        let err = parse_expr(r"SMA(AAPL.close, 0)").unwrap_err();
+        assert_eq!(Some(16..17), err.span());
+    }
+
+    #[test]
+    fn test_expected_descriptions() {
+        let err = parse_query("GET --1 FOR LAST 1 day STEP 1 hour").unwrap_err();
+        assert_eq!(
+            "Syntax error: unexpected end of input, expected expression at line 1, column 35",
+            err.to_string()
+        );
+
+        // rules are described, never named as in the grammar
+        let queries = [
+            "GET FOR LAST 1 day STEP 1 hour",
+            "GET NOT FOR LAST 1 day STEP 1 hour",
+            "GET AAPL. FOR LAST 1 day STEP 1 hour",
+            "GET {AAPL,}.close FOR LAST 1 day STEP 1 hour",
+            "GET @.close FOR LAST 1 day STEP 1 hour",
+            "GET AAPL.close AS 1 FOR LAST 1 day STEP 1 hour",
+            "GET CASE AAPL.close END FOR LAST 1 day STEP 1 hour",
+            "GET AAPL.close FOR LAST x day STEP 1 hour",
+            "GET AAPL.close FOR LAST 1 ages STEP 1 hour",
+            "GET AAPL.close FROM 2025 TO 2026 STEP 1 hour",
+            "GET AAPL.close FOR LAST 1 day STEP 1 hour ORDER BY",
+            "GET AAPL.close FOR LAST 1 day STEP 1 hour WHERE",
+            "GET AAPL.close FOR LAST 1 day STEP 1 hour SUMMARY LIMIT 1",
+            "GET AAPL.close + FOR LAST 1 day STEP 1 hour",
+            "GET (AAPL.close FOR LAST 1 day STEP 1 hour",
+        ];
+        for src in queries {
+            let err = parse_query(src).unwrap_err();
+            assert_eq!(&ErrorKind::Syntax, err.kind(), "{src}");
+            let message = err.to_string();
+            assert!(!err.expected().is_empty(), "{src}: {message}");
+            assert!(!message.contains('_'), "{src}: {message}");
+            assert!(!message.contains("not expr"), "{src}: {message}");
+        }
+    }
+
+    #[test]
+    fn test_symbol_sets() {
+        let query = parse_query(
+            r#"GET { XNAS:AAPL, "BRK.B" , "1X" }.close, {AAPL, MSFT}.max - {AAPL, MSFT}.min AS range
+               FOR LAST 1 day STEP 1 hour"#,
+        )
+        .unwrap();
+        let set = SymbolSet::List(vec!["XNAS:AAPL".into(), "BRK.B".into(), "1X".into()]);
+        assert_eq!(
+            &Expr::Data(SymbolMetric::of_set(set, Metric::CLOSE)),
+            query.columns()[0].expr()
+        );
+        assert_eq!(
+            r#"GET {XNAS:AAPL, "BRK.B", 1X}.close, {AAPL, MSFT}.max - {AAPL, MSFT}.min AS range FOR LAST 1 day STEP 1 hour"#,
+            query.to_string()
+        );
+        assert_eq!(query, parse_query(&query.to_string()).unwrap());
+
+        let err = parse_query("GET {AAPL, MSFT}.max - {AAPL}.min FOR LAST 1 day STEP 1 hour")
+            .unwrap_err();
+        assert_eq!(
+            &ErrorKind::SymbolSetMismatch("{AAPL, MSFT}".into(), "{AAPL}".into()),
+            err.kind()
+        );
+        assert_eq!(Some(23..29), err.span());
+
+        let err =
+            parse_query("GET AAPL.close FOR LAST 1 day STEP 1 hour WHERE {AAPL, MSFT}.close > 1")
+                .unwrap_err();
+        assert_eq!(
+            "Symbol set {AAPL, MSFT} is only allowed in GET columns at line 1, column 49",
+            err.to_string()
+        );
+        assert!(parse_expr("{AAPL, MSFT}.close").is_err());
+
+        let partial = parse_query_recovering(
+            "GET {AAPL, MSFT}.close, foo(AAPL.open) FOR LAST 1 day STEP 1 hour",
+            MetricCatalog::well_known(),
+        );
+        assert_eq!(1, partial.diagnostics().len());
+        assert_eq!(
+            "{AAPL, MSFT}.close",
+            partial.query().unwrap().columns()[0].to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_suggestions() {
+        let err = parse_query(r"GET AAPL.close FOR LAST 1 day STEPS 1 hour").unwrap_err();
+        assert_eq!(
+            &ErrorKind::Unknown("keyword".into(), "STEPS".into()),
+            err.kind()
+        );
+        assert_eq!(&["STEP".to_string()], err.suggestions());
+        assert_eq!(Some("STEPS"), err.token());
+
+        let err = parse_query(r"GET AAPL.close FOR LAST 2 dayz STEP 1 hour").unwrap_err();
+        assert_eq!(
+            &ErrorKind::Unknown("time unit".into(), "dayz".into()),
+            err.kind()
+        );
+        assert_eq!(&["day".to_string(), "days".to_string()], err.suggestions());
+
+        let err = parse_query(r"GET AAPL.close FOR LAST 1 day STEP 1 hour LIMIT x").unwrap_err();
+        assert!(err.suggestions().is_empty());
+    }
+
+    #[test]
+    fn test_case_insensitive_keywords_and_comments() {
+        let expected = parse_query(
+            r"GET AAPL.close AS c FOR LAST 2 days STEP 1 hour
+            WHERE NOT AAPL.close <= 1 AND (AAPL.close > 1 OR AAPL.open > 1) ORDER BY c DESC LIMIT 5",
+        )
+        .unwrap();
+
+        let src = r"get AAPL.close as c for  last 2 days Step 1 hour
+            where not AAPL.close <= 1 and (AAPL.close > 1 or AAPL.open > 1) order
+            by c desc limit 5";
+        assert_eq!(expected.to_string(), parse_query(src).unwrap().to_string());
+
+        let src = r"-- closing prices, daily
+            GET AAPL.close AS c /* WHERE, ORDER BY */ FOR LAST 2 days
+            STEP 1 hour -- hourly
+            WHERE NOT AAPL.close <= 1 AND (AAPL.close > 1 OR AAPL.open > 1) -- filter
+            ORDER BY c DESC LIMIT 5 -- top five";
+        assert_eq!(expected.to_string(), parse_query(src).unwrap().to_string());
+
+        let partial = parse_query_recovering(
+            r"get AAPL.close, foo(AAPL.open) /* , */ for last 2 days step 1 hour -- where",
+            MetricCatalog::well_known(),
+        );
+        assert_eq!(1, partial.diagnostics().len());
+        assert_eq!(1, partial.query().unwrap().columns().len());
+
+        assert!(parse_query(r"GET AAPL.close FOR LAST 1 day STEP 1 hour /* SUMMARY").is_err());
+    }
+
+    #[test]
+    fn test_parse_query_recovering() {
+        let src = r"GET SMA(AAPL.close, 0), AAPL.close AS c, foo(AAPL.open)
+            FOR LAST 1 day STEP 1 hour WHERE AAPL.close > LIMIT 5";
+        let partial = parse_query_recovering(src, MetricCatalog::well_known());
+        let kinds: Vec<&ErrorKind> = partial.diagnostics().iter().map(|e| e.kind()).collect();
+        assert_eq!(
+            vec![
+                &ErrorKind::InvalidValue("0".into(), "period".into()),
+                &ErrorKind::UnknownFunction("foo".into()),
+                &ErrorKind::Syntax,
+            ],
+            kinds
+        );
+        assert_eq!(Some((1, 42)), partial.diagnostics()[1].line_col());
+        assert_eq!(Some((2, 59)), partial.diagnostics()[2].line_col());
+
+        let query = partial.query().unwrap();
+        assert_eq!(1, query.columns().len());
+        assert_eq!(Some("c"), query.columns()[0].alias());
+        assert_eq!(None, query.filter());
+        assert_eq!(Some(5), query.limit());
+
+        let partial = parse_query_recovering(
+            r"GET AAPL.close FOR LAST 1 day WHERE AAPL.close > 1",
+            MetricCatalog::well_known(),
+        );
+        assert!(partial.query().is_none());
+        assert_eq!(
+            vec!["Syntax error: unexpected 'WHERE', expected STEP at line 1, column 31"],
+            partial
+                .diagnostics()
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        );
+
+        let partial = parse_query_recovering(
+            r"GET AAPL.close FOR LAST 1 day STEP 1 hour SUMMARY LIMIT 1",
+            MetricCatalog::well_known(),
+        );
+        assert_eq!(&ErrorKind::Syntax, partial.diagnostics()[0].kind());
+        assert_eq!(Some("LIMIT"), partial.diagnostics()[0].token());
+        assert!(partial.query().unwrap().summary());
+
+        let partial = parse_query_recovering(
+            r"GET AAPL.close FOR LAST 1 day STEP 1 hour LIMIT 5",
+            MetricCatalog::well_known(),
+        );
+        assert!(partial.diagnostics().is_empty());
+        assert_eq!(Some(5), partial.query().unwrap().limit());
+
+        // metrics are checked against the catalog, in valid queries too
+        let messages = |src: &str| -> Vec<String> {
+            let partial = parse_query_recovering(src, MetricCatalog::well_known());
+            partial
+                .diagnostics()
+                .iter()
+                .map(|e| e.to_string())
+                .collect()
+        };
+        assert_eq!(
+            vec![
+                "Unknown metric 'clsoe', did you mean 'close'? at line 1, column 5",
+                "Unknown function: foo at line 1, column 17",
+                "Unknown metric 'vwap' at line 2, column 36",
+            ],
+            messages(
+                "GET AAPL.clsoe, foo(AAPL.open)\n  FOR LAST 1 day STEP 1 hour WHERE MSFT.vwap > 1"
+            )
+        );
+        assert_eq!(
+            vec!["Unknown metric 'opne', did you mean 'open'? at line 1, column 56"],
+            messages("GET AAPL.close FOR LAST 1 day STEP 1 hour ORDER BY SMA(AAPL.opne, 3)")
+        );
+        assert_eq!(
+            vec![
+                "Unknown function: foo at line 1, column 5",
+                "Unknown metric 'opne', did you mean 'open'? at line 1, column 64",
+            ],
+            messages(
+                "GET foo(1), AAPL.close FOR LAST 1 day STEP 1 hour ORDER BY SMA(AAPL.opne, 3)"
+            )
+        );
+
+        let catalog = MetricCatalog::default().with(
+            Metric::new("vwap".to_string()),
+            MetricInfo::new("currency", Rollup::VolumeWeighted),
+        );
+        let partial = parse_query_recovering(
+            "GET AAPL.vwap, AAPL.vwpa FOR LAST 1 day STEP 1 hour",
+            &catalog,
+        );
+        assert_eq!(
+            vec!["Unknown metric 'vwpa', did you mean 'vwap'? at line 1, column 16"],
+            partial
+                .diagnostics()
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_window_function_invalid_period() {
+        for input in [
+            r"SMA(AAPL.close, 0)",
+            r"SMA(AAPL.close, 2.5)",
+            r"SMA(AAPL.close, AAPL.open)",
+            r"SMA(AAPL.close, 100001)",
+            r"LAG(AAPL.close, 1000000000000000000000000000000)",
+            r"STDDEV(AAPL.close, 1)",
+        ] {
+            assert!(matches!(
+                error_kind(parse_expr(input)),
+                Some(ErrorKind::InvalidValue(_, rule)) if rule == "period"
+            ));
+        }
+        assert_eq!(1, parse_expr(r"STDDEV(AAPL.close, 2)").unwrap().lookback());
+        assert_eq!(
+            100000,
+            parse_expr(r"LAG(AAPL.close, 100000)").unwrap().lookback()
+        );
+    }
+
     #[test]
     fn test_for_clause() {
         let input = r"FOR LAST 10 days";
         let time = parse_for_clause(input).unwrap();
         assert_eq!(10, time.value());
         assert_eq!(TimeUnit::Day, time.unit());
+
+        assert!(parse_for_clause(r"FORLAST 10 days").is_err());
+        assert!(parse_for_clause(r"FOR LAST10 days").is_err());
+        assert!(parse_query(r"GET AAPL.close FORLAST 1 day STEP 1 hour").is_err());
+        assert!(parse_query(r"GET AAPL.close FOR LAST 1 day STEP1 hour").is_err());
+    }
+
+    #[test]
+    fn test_from_clause() {
+        let input = r"FROM '2025-06-01T09:30:00Z' TO '2025-06-05T16:00:00+02:00'";
+        let range = parse_time_range(input).unwrap();
+        let from = "2025-06-01T09:30:00Z".parse().unwrap();
+        let to = "2025-06-05T14:00:00Z".parse().unwrap();
+        assert_eq!(TimeRange::Between(from, to), range);
+        assert_eq!(361800, range.length_at(Utc::now()));
+    }
+
+    #[test]
+    fn test_from_clause_invalid() {
+        assert!(parse_time_range(r"FROM '2025-06-01' TO '2025-06-05'").is_err());
+        assert!(
+            parse_time_range(r"FROM '2025-06-05T00:00:00Z' TO '2025-06-01T00:00:00Z'").is_err()
+        );
     }
 
     #[test]
@@ -98,6 +880,35 @@ mod test {
         assert_eq!(TimeUnit::Day, step.unit());
     }
 
+    #[test]
+    fn test_time_units() {
+        let step = parse_step_clause(r"STEP 5 minutes").unwrap();
+        assert_eq!(TimeUnit::Minute, step.unit());
+        assert_eq!(300, step.to_seconds());
+
+        let time = parse_for_clause(r"FOR LAST 2 weeks").unwrap();
+        assert_eq!(TimeUnit::Week, time.unit());
+        assert_eq!(14 * 86400, time.to_seconds());
+
+        let step = parse_step_clause(r"STEP 30 second").unwrap();
+        assert_eq!(TimeUnit::Second, step.unit());
+    }
+
+    #[test]
+    fn test_calendar_months() {
+        let time = parse_for_clause(r"FOR LAST 1 month").unwrap();
+        let now = "2025-03-31T12:00:00Z".parse().unwrap();
+        let expected = "2025-02-28T12:00:00Z".parse().unwrap();
+        assert_eq!(Some(expected), time.before(now));
+
+        let range = parse_time_range(r"FOR LAST 1 month").unwrap();
+        assert_eq!((expected, now), range.bounds(now));
+        let query = parse_query(r"GET AAPL.close FOR LAST 1 month STEP 1 day").unwrap();
+        assert_eq!(31, query.rows_count(now));
+        let now = "2025-03-15T12:00:00Z".parse().unwrap();
+        assert_eq!(28, query.rows_count(now));
+    }
+
     #[test]
     fn test_simple_query() {
         let input = r#"GET AAPL.open, AAPL.volume / 1000
@@ -108,4 +919,308 @@ mod test {
         let query = parse_query(input).unwrap();
         dbg!(query);
     }
+
+    #[test]
+    fn test_column_alias() {
+        let input = r"GET AAPL.max - AAPL.min AS spread, AAPL.close FOR LAST 1 day STEP 1 hour";
+        let query = parse_query(input).unwrap();
+        let columns = query.columns();
+        assert_eq!(Some("spread"), columns[0].alias());
+        assert_eq!("spread", columns[0].name());
+        assert_eq!(None, columns[1].alias());
+        assert_eq!("AAPL.close", columns[1].name());
+        assert_eq!(input, query.to_string());
+    }
+
+    #[test]
+    fn test_absolute_range_query() {
+        let input =
+            r"GET AAPL.close FROM '2025-06-01T09:30:00Z' TO '2025-06-05T16:00:00Z' STEP 1 hour";
+        let query = parse_query(input).unwrap();
+        assert_eq!(102, query.rows_count(Utc::now()));
+        assert_eq!(input, query.to_string());
+    }
+
+    #[test]
+    fn test_canonical_format() {
+        for input in [
+            r"(AAPL.max - AAPL.min) / 2",
+            r"AAPL.max - AAPL.min / 2",
+            r"AAPL.close - (AAPL.open - 1)",
+            r"AAPL.close - AAPL.open - 1",
+            r"-(AAPL.close - 1) * -2",
+            r"-(-AAPL.close)",
+            r"(AAPL.close > 1) = (AAPL.open > 1)",
+            r"NOT (AAPL.close > 1 OR AAPL.open > 1) AND NOT AAPL.max > 2",
+            r"(AAPL.close > 1 OR AAPL.open > 1) AND (AAPL.max > 2 OR AAPL.min > 2)",
+            r"AAPL.close > 1 OR AAPL.open > 1 AND AAPL.max > 2",
+            r"(NOT AAPL.close > 1) + 1",
+        ] {
+            assert_eq!(input, parse_expr(input).unwrap().to_string());
+        }
+
+        for input in [
+            r"NOT (AAPL.close > 1 OR AAPL.open > 1) AND NOT AAPL.max > 2",
+            r"AAPL.close > 1 OR (AAPL.open > 1 OR AAPL.max > 2)",
+            r"AAPL.close > 1 AND (AAPL.open > 1 AND AAPL.max > 2)",
+            r"(AAPL.close > 1) > AAPL.open + 1",
+        ] {
+            assert_eq!(input, parse_condition(input).unwrap().to_string());
+        }
+
+        let query = parse_query(
+            r"get ((AAPL.max-AAPL.min))/2 as half_spread for  last 1 day
+            step 1 hour where (AAPL.close>1) order by half_spread desc limit 3",
+        )
+        .unwrap();
+        assert_eq!(
+            r"GET (AAPL.max - AAPL.min) / 2 AS half_spread FOR LAST 1 day STEP 1 hour WHERE AAPL.close > 1 ORDER BY half_spread DESC LIMIT 3",
+            query.to_string()
+        );
+    }
+
+    /// Random queries, as built by the parser, for round-trip tests.
+    mod generator {
+        use super::*;
+        use rand::{Rng, rngs::StdRng};
+
+        const SYMBOLS: &[&str] = &["AAPL", "MSFT", "ORCL", "A1", "BRK.B", "RDS-A"];
+        const EXCHANGES: &[Option<&str>] = &[None, None, Some("XNAS"), Some("XNYS")];
+        const METRICS: &[Metric] = &[
+            Metric::OPEN,
+            Metric::CLOSE,
+            Metric::MAX,
+            Metric::MIN,
+            Metric::VOLUME,
+            Metric::AVG,
+        ];
+        const OPERATORS: &[Operator] = &[
+            Operator::Add,
+            Operator::Sub,
+            Operator::Mul,
+            Operator::Div,
+            Operator::Compare(Comparison::Lt),
+            Operator::Compare(Comparison::Ne),
+            Operator::And,
+            Operator::Or,
+        ];
+        const COMPARISONS: &[Operator] = &[
+            Operator::Compare(Comparison::Eq),
+            Operator::Compare(Comparison::Ne),
+            Operator::Compare(Comparison::Lt),
+            Operator::Compare(Comparison::Le),
+            Operator::Compare(Comparison::Gt),
+            Operator::Compare(Comparison::Ge),
+        ];
+
+        fn pick<T: Clone>(rng: &mut StdRng, items: &[T]) -> T {
+            items[rng.random_range(0..items.len())].clone()
+        }
+
+        fn data(rng: &mut StdRng) -> Expr {
+            let data = SymbolMetric::new(pick(rng, SYMBOLS), pick(rng, METRICS));
+            Expr::Data(data.with_exchange(pick(rng, EXCHANGES)))
+        }
+
+        /// Number: mostly small, also of large or tiny magnitudes and the extremes of f64.
+        pub fn number(rng: &mut StdRng) -> f64 {
+            match rng.random_range(0..4) {
+                0 => {
+                    rng.random_range(1..1000) as f64 / 100.0
+                        * 10f64.powi(rng.random_range(-300..=300))
+                }
+                1 => pick(rng, &[f64::MAX, f64::MIN_POSITIVE, 1e308, 5e-324]),
+                _ => rng.random_range(0..400) as f64 / 8.0,
+            }
+        }
+
+        /// Source of a number literal with an exponent (`4.2e-17`), at times beyond f64.
+        pub fn exponent_literal(rng: &mut StdRng) -> String {
+            format!(
+                "{}.{}{}{}{}",
+                rng.random_range(0..10),
+                rng.random_range(0..100),
+                pick(rng, &["e", "E"]),
+                pick(rng, &["", "+", "-"]),
+                rng.random_range(0..400)
+            )
+        }
+
+        pub fn expr(rng: &mut StdRng, depth: u32) -> Expr {
+            if depth == 0 || rng.random_bool(0.2) {
+                return match rng.random_bool(0.3) {
+                    true => Expr::Value(number(rng)),
+                    false => data(rng),
+                };
+            }
+            let sub = |rng: &mut StdRng| Box::new(expr(rng, depth - 1));
+            match rng.random_range(0..8) {
+                // the parser folds negative literals into values
+                0 => match *sub(rng) {
+                    Expr::Value(val) => Expr::Value(-val),
+                    other => Expr::Neg(Box::new(other)),
+                },
+                1 => Expr::Not(sub(rng)),
+                2 => {
+                    let branches = vec![(*sub(rng), *sub(rng)), (*sub(rng), *sub(rng))];
+                    let default = rng.random_bool(0.5).then(|| sub(rng));
+                    Expr::Case(branches, default)
+                }
+                3 => Expr::Call(Function::Abs, vec![*sub(rng)]),
+                4 => {
+                    let series = *sub(rng);
+                    let period = Expr::Value(rng.random_range(1..30) as f64);
+                    Expr::Call(Function::Sma, vec![series, period])
+                }
+                5 => Expr::Call(Function::Max, vec![*sub(rng), *sub(rng)]),
+                _ => {
+                    let left = sub(rng);
+                    Expr::Binary(left, pick(rng, OPERATORS), sub(rng))
+                }
+            }
+        }
+
+        /// Boolean expression: comparisons combined with AND, OR and NOT.
+        pub fn condition(rng: &mut StdRng, depth: u32) -> Expr {
+            if depth == 0 || rng.random_bool(0.3) {
+                let left = Box::new(expr(rng, 2));
+                return Expr::Binary(left, pick(rng, COMPARISONS), Box::new(expr(rng, 2)));
+            }
+            let sub = |rng: &mut StdRng| Box::new(condition(rng, depth - 1));
+            match rng.random_range(0..3) {
+                0 => Expr::Not(sub(rng)),
+                1 => Expr::Binary(sub(rng), Operator::And, sub(rng)),
+                _ => Expr::Binary(sub(rng), Operator::Or, sub(rng)),
+            }
+        }
+
+        pub fn query(rng: &mut StdRng) -> Query {
+            let columns: Vec<Column> = (0..rng.random_range(1..4))
+                .map(|i| {
+                    let alias = rng.random_bool(0.5).then(|| format!("col_{}", i));
+                    Column::new(expr(rng, 4), alias)
+                })
+                .collect();
+            let range = match rng.random_bool(0.7) {
+                true => TimeRange::Last(TimeSpec::new(rng.random_range(1..10), TimeUnit::Day)),
+                false => TimeRange::Between(
+                    "2025-06-01T09:30:00Z".parse().unwrap(),
+                    "2025-06-05T16:00:00Z".parse().unwrap(),
+                ),
+            };
+            let step = TimeSpec::new(2, TimeUnit::Hour);
+            let resample = rng
+                .random_bool(0.3)
+                .then(|| TimeSpec::new(pick(rng, &[1, 15, 30]), TimeUnit::Minute));
+            let filter = rng.random_bool(0.5).then(|| condition(rng, 3));
+
+            let order = match rng.random_bool(0.5) {
+                true => SortOrder::Desc,
+                false => SortOrder::Asc,
+            };
+            let order_by = match (rng.random_range(0..3), columns[0].alias()) {
+                (0, Some(alias)) => Some(OrderBy::new(SortKey::Alias(alias.to_string()), order)),
+                (1, _) => Some(OrderBy::new(SortKey::Expr(expr(rng, 2)), order)),
+                _ => None,
+            };
+            let limit = rng.random_bool(0.5).then(|| rng.random_range(1..100));
+            let offset = match limit {
+                Some(_) => rng.random_range(0..3),
+                None => 0,
+            };
+
+            Query::new(columns, range, step)
+                .with_resample(resample)
+                .with_filter(filter)
+                .with_order_by(order_by)
+                .with_limit(limit, offset)
+                .with_summary(rng.random_bool(0.5))
+        }
+    }
+
+    #[test]
+    fn test_format_round_trip() {
+        use rand::{SeedableRng, rngs::StdRng};
+
+        let mut rng = StdRng::seed_from_u64(17);
+        for _ in 0..500 {
+            let query = generator::query(&mut rng);
+            let src = query.to_string();
+            assert_eq!(Ok(&query), parse_query(&src).as_ref(), "{}", src);
+        }
+        for _ in 0..500 {
+            let expr = generator::expr(&mut rng, 6);
+            let src = expr.to_string();
+            assert_eq!(Ok(&expr), parse_expr(&src).as_ref(), "{}", src);
+        }
+
+        // literals are parsed as f64 does, those too large to be finite are rejected
+        for _ in 0..500 {
+            let src = generator::exponent_literal(&mut rng);
+            let number: f64 = src.parse().unwrap();
+            match number.is_finite() {
+                true => {
+                    let expr = parse_expr(&src).unwrap();
+                    assert_eq!(Expr::Value(number), expr, "{}", src);
+                    assert_eq!(Ok(&expr), parse_expr(&expr.to_string()).as_ref(), "{}", src);
+                }
+                false => assert!(parse_expr(&src).is_err(), "{}", src),
+            }
+        }
+        assert!(parse_expr("1e400").is_err());
+        assert!(
+            parse_query("GET AAPL.close FOR LAST 1 day STEP 1 hour WHERE AAPL.close > 1e400")
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_json_shape() {
+        let query = parse_query(
+            "GET SMA(AAPL.close, 5) / 2 AS sma, @portfolio.open \
+             FROM '2025-06-01T00:00:00Z' TO '2025-06-02T00:00:00Z' \
+             STEP 1 hour WHERE NOT AAPL.volume >= 10 ORDER BY sma DESC LIMIT 3",
+        )
+        .unwrap();
+        let json = serde_json::json!({
+            "columns": [{
+                "expr": {"binary": [
+                    {"call": ["SMA", [
+                        {"data": {"symbol": "AAPL", "metric": "close"}},
+                        {"value": 5.0}
+                    ]]},
+                    "div",
+                    {"value": 2.0}
+                ]},
+                "alias": "sma"
+            }, {
+                "expr": {"data": {"set": {"universe": "portfolio"}, "metric": "open"}}
+            }],
+            "range": {"between": ["2025-06-01T00:00:00Z", "2025-06-02T00:00:00Z"]},
+            "step": {"value": 1, "unit": "hour"},
+            "filter": {"not": {"binary": [
+                {"data": {"symbol": "AAPL", "metric": "volume"}},
+                {"compare": "ge"},
+                {"value": 10.0}
+            ]}},
+            "order_by": {"key": {"alias": "sma"}, "order": "desc"},
+            "limit": 3
+        });
+        assert_eq!(json, serde_json::to_value(&query).unwrap());
+        assert_eq!(query, serde_json::from_value(json).unwrap());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        use rand::{SeedableRng, rngs::StdRng};
+
+        let mut rng = StdRng::seed_from_u64(17);
+        for _ in 0..200 {
+            let query = generator::query(&mut rng);
+            let json = serde_json::to_string(&query).unwrap();
+            assert_eq!(query, serde_json::from_str(&json).unwrap(), "{}", json);
+        }
+    }
 }
@@ -0,0 +1,144 @@
+use super::model::{Metric, TimeUnit};
+
+/// Keywords of the query language, as written in the grammar.
+pub(crate) const KEYWORDS: &[&str] = &[
+    "GET", "FOR", "LAST", "FROM", "TO", "STEP", "RESAMPLE", "WHERE", "ORDER", "BY", "ASC", "DESC",
+    "LIMIT", "OFFSET", "SUMMARY", "AS", "AND", "OR", "NOT", "CASE", "WHEN", "THEN", "ELSE", "END",
+];
+
+/// Known words, by the name of what they denote (as used in error messages).
+const VOCABULARIES: &[(&str, &[&str])] = &[
+    ("keyword", KEYWORDS),
+    ("metric", Metric::NAMES),
+    ("time unit", TimeUnit::NAMES),
+];
+
+/// Words of `vocabulary` closest to `word` (ignoring case), if any is close enough
+/// to be a likely typo.
+pub(crate) fn suggest(word: &str, vocabulary: &[&str]) -> Vec<String> {
+    closest(word, vocabulary)
+        .map(|(_, words)| words.into_iter().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Finds what a misspelled `word` was likely meant to be, looking in the vocabularies
+/// named in `expected` (or in all of them). Returns what the word denotes
+/// (i.e. "metric") and the suggested replacements.
+pub(crate) fn suggest_any(word: &str, expected: &[String]) -> Option<(&'static str, Vec<String>)> {
+    // keywords are case-insensitive, metrics and units are not
+    if KEYWORDS
+        .iter()
+        .any(|keyword| keyword.eq_ignore_ascii_case(word))
+        || VOCABULARIES
+            .iter()
+            .any(|(_, vocabulary)| vocabulary.contains(&word))
+    {
+        return None;
+    }
+    let restricted = VOCABULARIES
+        .iter()
+        .any(|(name, _)| expected.iter().any(|e| e == name));
+
+    VOCABULARIES
+        .iter()
+        .filter(|(name, _)| !restricted || expected.iter().any(|e| e == name))
+        .filter_map(|(name, vocabulary)| {
+            closest(word, vocabulary).map(|(distance, words)| (distance, *name, words))
+        })
+        .min_by_key(|(distance, _, _)| *distance)
+        .map(|(_, name, words)| (name, words.into_iter().map(str::to_string).collect()))
+}
+
+/// Words at the smallest distance from `word`, with the distance, provided
+/// it is below a third of the word length (and at least one edit).
+fn closest<'a>(word: &str, vocabulary: &[&'a str]) -> Option<(usize, Vec<&'a str>)> {
+    let word = word.to_lowercase();
+    let len = word.chars().count();
+    if len < 2 {
+        return None;
+    }
+    let max_distance = (len / 3).max(1);
+
+    let mut best: Option<(usize, Vec<&str>)> = None;
+    for candidate in vocabulary {
+        let distance = edit_distance(&word, &candidate.to_lowercase());
+        if distance > max_distance {
+            continue;
+        }
+        match &mut best {
+            Some((best_distance, words)) if distance == *best_distance => words.push(candidate),
+            Some((best_distance, _)) if distance > *best_distance => {}
+            _ => best = Some((distance, vec![candidate])),
+        }
+    }
+    best
+}
+
+/// Optimal string alignment distance: the number of insertions, deletions,
+/// substitutions and transpositions of adjacent characters turning `a` into `b`.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // rows i-2, i-1 and i of the distance matrix
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current: Vec<usize> = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(0, edit_distance("close", "close"));
+        assert_eq!(1, edit_distance("clsoe", "close"));
+        assert_eq!(1, edit_distance("steps", "step"));
+        assert_eq!(2, edit_distance("dya", "days"));
+        assert_eq!(5, edit_distance("", "close"));
+    }
+
+    #[test]
+    fn test_suggest() {
+        assert_eq!(vec!["close"], suggest("clsoe", Metric::NAMES));
+        assert_eq!(vec!["day", "days"], suggest("dayz", TimeUnit::NAMES));
+        assert!(suggest("price", Metric::NAMES).is_empty());
+
+        assert_eq!(
+            Some(("keyword", vec!["STEP".to_string()])),
+            suggest_any("STEPS", &[])
+        );
+        assert_eq!(
+            Some(("keyword", vec!["WHERE".to_string()])),
+            suggest_any("wehre", &[])
+        );
+        assert_eq!(None, suggest_any("STEP", &[]));
+        assert_eq!(None, suggest_any("step", &[]));
+        assert_eq!(
+            Some(("metric", vec!["close".to_string()])),
+            suggest_any("Close", &[])
+        );
+        assert_eq!(None, suggest_any("x", &[]));
+        assert_eq!(
+            Some(("metric", vec!["max".to_string()])),
+            suggest_any("mx", &["metric".to_string()])
+        );
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::BTreeMap;
+
+use super::{
+    model::{Column, Expr, Query, SortKey, SymbolMetric, SymbolSet, split_exchange, symbol_alias},
+    suggest::suggest,
+    validate::{ValidationIssue, collect_subexpressions},
+};
+
+/// Named sets of symbols, i.e. a portfolio or a sector basket, which queries refer to
+/// as `@name` (`@portfolio.close`). A symbol may be qualified by its exchange (`XNAS:AAPL`).
+/// Sets listed in queries (`{AAPL, MSFT}.close`) are expanded along with the universes.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Universes {
+    universes: BTreeMap<String, Vec<String>>,
+}
+
+impl Universes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, name: &str, symbols: &[&str]) -> Self {
+        let symbols = symbols.iter().map(|symbol| symbol.to_string()).collect();
+        self.universes.insert(name.to_string(), symbols);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[String]> {
+        self.universes.get(name).map(Vec::as_slice)
+    }
+
+    /// Universes with their symbols, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.universes
+            .iter()
+            .map(|(name, symbols)| (name.as_str(), symbols.as_slice()))
+    }
+
+    /// Expands the columns referring to a set of symbols, listed (`{AAPL, MSFT}.close`) or
+    /// a universe (`@portfolio.close`), into a column per symbol of the set. Each expanded
+    /// column is aliased by the alias of the column and the symbol (`@portfolio.close AS c`
+    /// into `AAPL.close AS c_AAPL`, ...); a column without an alias gets one from the
+    /// functions and metrics of the set it refers to (`@portfolio.close` into
+    /// `AAPL.close AS close_AAPL`, `SMA(@portfolio.close, 5)` into `sma_close_AAPL`),
+    /// numbered if taken by an earlier column (`close_AAPL_2`). Fails if a universe is
+    /// unknown, if a column refers to different sets, if a set is referred to outside the columns or if ORDER BY refers
+    /// to the alias of a column to expand.
+    pub fn expand(&self, query: &Query) -> Result<Query, Vec<ValidationIssue>> {
+        let mut issues = Vec::new();
+
+        // the parser allows sets only in columns, other queries may have them anywhere
+        let order_by = query.order_by().and_then(|order_by| order_by.expr());
+        for expr in query.filter().into_iter().chain(order_by) {
+            for set in symbol_sets(expr) {
+                issues.push(match set {
+                    SymbolSet::Universe(name) => ValidationIssue::MisplacedUniverse(name.clone()),
+                    list => ValidationIssue::MisplacedSymbolSet(list.to_string()),
+                });
+            }
+        }
+
+        // the alias is given to each column of the expansion, with the symbol appended
+        if let Some(SortKey::Alias(alias)) = query.order_by().map(|order_by| order_by.key())
+            && let Some(column) = query.columns().iter().find(|c| c.alias() == Some(alias))
+            && let Some(set) = symbol_sets(column.expr()).first()
+        {
+            issues.push(ValidationIssue::ExpandedAlias(
+                alias.clone(),
+                set.to_string(),
+            ));
+        }
+
+        let mut columns = Vec::new();
+        for column in query.columns() {
+            let symbols = match symbol_sets(column.expr()).as_slice() {
+                [] => {
+                    columns.push(column.clone());
+                    continue;
+                }
+                [SymbolSet::List(symbols)] => symbols.as_slice(),
+                [SymbolSet::Universe(name)] => match self.get(name) {
+                    Some(symbols) => symbols,
+                    None => {
+                        let names: Vec<&str> = self.universes.keys().map(String::as_str).collect();
+                        let suggestions = suggest(name, &names);
+                        issues.push(ValidationIssue::UnknownUniverse(
+                            name.to_string(),
+                            suggestions,
+                        ));
+                        continue;
+                    }
+                },
+                [SymbolSet::Universe(first), SymbolSet::Universe(second), ..] => {
+                    issues.push(ValidationIssue::UniverseMismatch(
+                        first.to_string(),
+                        second.to_string(),
+                    ));
+                    continue;
+                }
+                [first, second, ..] => {
+                    issues.push(ValidationIssue::SymbolSetMismatch(
+                        first.to_string(),
+                        second.to_string(),
+                    ));
+                    continue;
+                }
+            };
+            for symbol in symbols {
+                let expanded = expand_column(column, symbol);
+                let expanded = match column.alias() {
+                    Some(_) => expanded,
+                    None => {
+                        let alias = unique_alias(expanded.name(), &columns);
+                        Column::new(expanded.expr().clone(), Some(alias))
+                    }
+                };
+                columns.push(expanded);
+            }
+        }
+
+        match issues.is_empty() {
+            true => Ok(query.clone().with_columns(columns)),
+            false => Err(issues),
+        }
+    }
+}
+
+/// Sets of symbols an expression refers to, without repetitions.
+fn symbol_sets(expr: &Expr) -> Vec<&SymbolSet> {
+    let mut exprs = Vec::new();
+    collect_subexpressions(expr, &mut exprs);
+    let mut sets = Vec::new();
+    for expr in exprs {
+        if let Expr::Data(sm) = expr
+            && let Some(set) = sm.set()
+            && !sets.contains(&set)
+        {
+            sets.push(set);
+        }
+    }
+    sets
+}
+
+/// The column for a symbol (`XNAS:AAPL`) of the set it refers to, aliased (see
+/// `symbol_alias`) by the alias of the column or, without one, by its `base_name`.
+fn expand_column(column: &Column, symbol: &str) -> Column {
+    let (exchange, symbol) = split_exchange(symbol);
+    let expr = column.expr().map_data(&mut |sm| match sm.set() {
+        Some(_) => SymbolMetric::new(symbol, sm.metric().clone())
+            .with_exchange(exchange)
+            .with_span(sm.span()),
+        None => sm.clone(),
+    });
+    let alias = match column.alias() {
+        Some(alias) => symbol_alias(alias, exchange, symbol),
+        None => symbol_alias(&base_name(column.expr()), exchange, symbol),
+    };
+    Column::new(expr, Some(alias))
+}
+
+/// Name of an expression referring to a set, for the aliases of its expansion: the
+/// functions (in lowercase) and the metrics of the set it refers to, in order, joined by `_`
+/// (`max_min` for `@portfolio.max - @portfolio.min`).
+fn base_name(expr: &Expr) -> String {
+    let mut exprs = Vec::new();
+    collect_subexpressions(expr, &mut exprs);
+    let words: Vec<String> = exprs
+        .into_iter()
+        .filter_map(|expr| match expr {
+            Expr::Call(func, _) => Some(func.to_string().to_lowercase()),
+            Expr::Data(sm) if sm.set().is_some() => Some(sm.metric().to_string()),
+            _ => None,
+        })
+        .collect();
+    words.join("_")
+}
+
+/// The `alias`, numbered (`close_AAPL_2`, ...) if it names one of the `columns` already.
+fn unique_alias(alias: String, columns: &[Column]) -> String {
+    let taken = |name: &str| columns.iter().any(|column| column.name() == name);
+    if !taken(&alias) {
+        return alias;
+    }
+    (2..)
+        .map(|n| format!("{alias}_{n}"))
+        .find(|alias| !taken(alias))
+        .unwrap_or(alias)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parse_expr, parse_query};
+
+    #[test]
+    fn test_expand() {
+        let universes = Universes::new()
+            .with("portfolio", &["AAPL", "XNAS:MSFT"])
+            .with("energy", &["XOM"]);
+        let parse = |src: &str| parse_query(&format!("{src} FOR LAST 1 day STEP 1 hour")).unwrap();
+
+        let query =
+            parse("GET @portfolio.max - @portfolio.min AS range, @energy.close / SPY.close");
+        assert_eq!(
+            "GET @portfolio.max - @portfolio.min AS range, @energy.close / SPY.close \
+             FOR LAST 1 day STEP 1 hour",
+            query.to_string()
+        );
+        assert_eq!(
+            Ok(parse(
+                "GET AAPL.max - AAPL.min AS range_AAPL, XNAS:MSFT.max - XNAS:MSFT.min AS range_XNAS_MSFT, \
+                 XOM.close / SPY.close AS close_XOM"
+            )),
+            universes.expand(&query)
+        );
+        assert_eq!(
+            Ok(parse("GET AAPL.close")),
+            universes.expand(&parse("GET AAPL.close"))
+        );
+
+        let query = parse("GET @portfolo.close, @portfolio.close / @energy.close");
+        assert_eq!(
+            Err(vec![
+                ValidationIssue::UnknownUniverse("portfolo".into(), vec!["portfolio".into()]),
+                ValidationIssue::UniverseMismatch("portfolio".into(), "energy".into()),
+            ]),
+            universes.expand(&query)
+        );
+
+        let condition = parse_expr("@energy.close > 1").unwrap_err();
+        assert_eq!(
+            "Universe @energy is only allowed in GET columns at line 1, column 1",
+            condition.to_string()
+        );
+        let query = parse("GET AAPL.close").with_filter(Some(
+            Expr::Data(SymbolMetric::universe("energy", crate::Metric::CLOSE)).gt(1),
+        ));
+        assert_eq!(
+            Err(vec![ValidationIssue::MisplacedUniverse("energy".into())]),
+            universes.expand(&query)
+        );
+
+        let sorted = |key: &str| {
+            let src = "GET @portfolio.close AS c, SPY.close AS spy FOR LAST 1 day STEP 1 hour";
+            parse_query(&format!("{src} ORDER BY {key}")).unwrap()
+        };
+        let query = sorted("c");
+        let issues = universes.expand(&query).unwrap_err();
+        assert_eq!(
+            vec![ValidationIssue::ExpandedAlias(
+                "c".into(),
+                "@portfolio".into()
+            )],
+            issues
+        );
+        assert_eq!(
+            "ORDER BY refers to c, the alias of a column expanded for each symbol of @portfolio",
+            issues[0].to_string()
+        );
+        assert!(universes.expand(&sorted("spy")).is_ok());
+    }
+
+    #[test]
+    fn test_expand_symbol_sets() {
+        let parse = |src: &str| parse_query(&format!("{src} FOR LAST 1 day STEP 1 hour")).unwrap();
+        let expand = |src: &str| Universes::new().expand(&parse(src)).unwrap();
+
+        // columns without an alias are aliased by the metrics and functions of the set,
+        // numbered if the alias is taken
+        let query = expand("GET {AAPL, MSFT}.close, {AAPL, MSFT}.open, {AAPL}.close + 1");
+        let names: Vec<String> = query.columns().iter().map(Column::name).collect();
+        assert_eq!(
+            vec![
+                "close_AAPL",
+                "close_MSFT",
+                "open_AAPL",
+                "open_MSFT",
+                "close_AAPL_2"
+            ],
+            names
+        );
+        assert_eq!(
+            parse(
+                "GET AAPL.close AS close_AAPL, MSFT.close AS close_MSFT, AAPL.open AS open_AAPL, \
+                 MSFT.open AS open_MSFT, AAPL.close + 1 AS close_AAPL_2"
+            ),
+            query
+        );
+        assert_eq!(
+            parse(
+                "GET AAPL.max - AAPL.min AS range_AAPL, MSFT.max - MSFT.min AS range_MSFT, \
+                 SMA(AAPL.close / AAPL.close, 5) AS sma_close_AAPL, \
+                 SMA(MSFT.close / AAPL.close, 5) AS sma_close_MSFT"
+            ),
+            expand(
+                "GET {AAPL, MSFT}.max - {AAPL, MSFT}.min AS range, \
+                 SMA({AAPL, MSFT}.close / AAPL.close, 5)"
+            )
+        );
+        assert_eq!(
+            parse(r#"GET XNAS:AAPL.close AS c_XNAS_AAPL, "BRK.B".close AS c_BRK_B"#),
+            expand(r#"GET {XNAS:AAPL, "BRK.B"}.close AS c"#)
+        );
+
+        // sets mixed with universes, or anywhere in queries not parsed
+        let universes = Universes::new().with("portfolio", &["AAPL", "MSFT"]);
+        let set = SymbolSet::List(vec!["AAPL".into(), "MSFT".into()]);
+        let query = parse("GET {AAPL, MSFT}.close / @portfolio.close").with_filter(Some(
+            Expr::Data(SymbolMetric::of_set(set, crate::Metric::CLOSE)).gt(1),
+        ));
+        assert_eq!(
+            Err(vec![
+                ValidationIssue::MisplacedSymbolSet("{AAPL, MSFT}".into()),
+                ValidationIssue::SymbolSetMismatch("{AAPL, MSFT}".into(), "@portfolio".into()),
+            ]),
+            universes.expand(&query)
+        );
+    }
+}
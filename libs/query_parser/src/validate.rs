@@ -0,0 +1,452 @@
+use std::{borrow::Cow, ops::Range};
+
+use chrono::{DateTime, Utc};
+
+use super::{
+    catalog::MetricCatalog,
+    error::did_you_mean,
+    model::{Expr, Function, Operator, Query, SortKey, SymbolMetric},
+};
+
+/// Maximum number of rows a query may produce.
+pub const MAX_ROWS: usize = 100_000;
+
+/// Problem found in a parsed query by [`validate`]. Errors make the query impossible
+/// or pointless to run; warnings point at a likely mistake.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ValidationIssue {
+    #[error("Zero value: {0} must be positive")]
+    ZeroValue(Cow<'static, str>),
+
+    #[error("Query selects no columns")]
+    NoColumns,
+
+    #[error("Resample bar {0} does not split the step into several whole bars")]
+    InvalidResample(String),
+
+    #[error("Invalid call {0}: {1}")]
+    InvalidCall(String, Cow<'static, str>),
+
+    #[error("ORDER BY refers to unknown column alias {0}")]
+    UnknownAlias(String),
+
+    #[error("Step {0} is longer than the time range")]
+    StepExceedsRange(String),
+
+    #[error("Time range is not a multiple of the step {0}, its remainder is not covered by a row")]
+    PartialStep(String),
+
+    #[error("Query computes {0} rows including the warm-up, more than the maximum of {1}")]
+    TooManyRows(usize, usize),
+
+    #[error("Expression {0} is selected more than once")]
+    DuplicateExpression(String),
+
+    #[error("Alias {0} is given to more than one column")]
+    DuplicateAlias(String),
+
+    #[error("Division by zero in {0}")]
+    DivisionByZero(String),
+
+    #[error("Unknown metric '{0}'{suggestions}", suggestions = did_you_mean(.1))]
+    UnknownMetric(String, Vec<String>, Option<Range<usize>>),
+
+    #[error("Unknown universe '@{0}'{suggestions}", suggestions = did_you_mean(.1))]
+    UnknownUniverse(String, Vec<String>),
+
+    #[error("Universes of a column must be the same, but found @{0} and @{1}")]
+    UniverseMismatch(String, String),
+
+    #[error("Universe @{0} is only allowed in GET columns")]
+    MisplacedUniverse(String),
+
+    #[error("Symbol sets of a column must be the same, but found {0} and {1}")]
+    SymbolSetMismatch(String, String),
+
+    #[error("Symbol set {0} is only allowed in GET columns")]
+    MisplacedSymbolSet(String),
+
+    #[error("ORDER BY refers to {0}, the alias of a column expanded for each symbol of {1}")]
+    ExpandedAlias(String, String),
+}
+
+/// Whether an issue prevents the query from running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl ValidationIssue {
+    pub fn severity(&self) -> Severity {
+        use ValidationIssue::*;
+        match self {
+            ZeroValue(_)
+            | NoColumns
+            | InvalidResample(_)
+            | InvalidCall(..)
+            | UnknownAlias(_)
+            | StepExceedsRange(_)
+            | TooManyRows(..)
+            | UnknownMetric(..)
+            | UnknownUniverse(..)
+            | UniverseMismatch(..)
+            | MisplacedUniverse(_)
+            | SymbolSetMismatch(..)
+            | MisplacedSymbolSet(_)
+            | ExpandedAlias(..) => Severity::Error,
+            PartialStep(_) | DuplicateExpression(_) | DuplicateAlias(_) | DivisionByZero(_) => {
+                Severity::Warning
+            }
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity() == Severity::Error
+    }
+
+    /// Stable identifier of the kind of the issue, i.e. `step_exceeds_range`.
+    pub fn code(&self) -> &'static str {
+        use ValidationIssue::*;
+        match self {
+            ZeroValue(_) => "zero_value",
+            NoColumns => "no_columns",
+            InvalidResample(_) => "invalid_resample",
+            InvalidCall(..) => "invalid_call",
+            UnknownAlias(_) => "unknown_alias",
+            StepExceedsRange(_) => "step_exceeds_range",
+            PartialStep(_) => "partial_step",
+            TooManyRows(..) => "too_many_rows",
+            DuplicateExpression(_) => "duplicate_expression",
+            DuplicateAlias(_) => "duplicate_alias",
+            DivisionByZero(_) => "division_by_zero",
+            UnknownMetric(..) => "unknown_metric",
+            UnknownUniverse(..) => "unknown_universe",
+            UniverseMismatch(..) => "universe_mismatch",
+            MisplacedUniverse(_) => "misplaced_universe",
+            SymbolSetMismatch(..) => "symbol_set_mismatch",
+            MisplacedSymbolSet(_) => "misplaced_symbol_set",
+            ExpandedAlias(..) => "expanded_alias",
+        }
+    }
+
+    /// Byte range of the offending part of the source, where known
+    /// (the first use of an unknown metric in a parsed query).
+    pub fn span(&self) -> Option<Range<usize>> {
+        match self {
+            ValidationIssue::UnknownMetric(_, _, span) => span.clone(),
+            _ => None,
+        }
+    }
+
+    /// Messages of the errors among the `issues`, separated by semicolons.
+    pub fn errors_message(issues: &[ValidationIssue]) -> String {
+        let errors: Vec<String> = issues
+            .iter()
+            .filter(|issue| issue.is_error())
+            .map(ToString::to_string)
+            .collect();
+        errors.join("; ")
+    }
+}
+
+/// Checks a parsed query before it is planned and run: time spans must not
+/// be zero, the range must hold at least one step and the number of
+/// rows must be reasonable. Also warns about a range that is not a multiple of
+/// the step, expressions selected twice, aliases given twice and division by a zero literal.
+/// The metrics referred to must be in the `catalog`.
+///
+/// Queries built other than by parsing (i.e. deserialized or built in code) are
+/// also checked for what the parser guarantees: columns, a valid resample bar,
+/// function arguments and the aliases ORDER BY refers to.
+/// Returns the issues found, errors first.
+pub fn validate(query: &Query, catalog: &MetricCatalog) -> Vec<ValidationIssue> {
+    validate_at(query, catalog, Utc::now())
+}
+
+/// Validates the query (see `validate`) as executed at `now`, which relative
+/// ranges are resolved against.
+pub fn validate_at(
+    query: &Query,
+    catalog: &MetricCatalog,
+    now: DateTime<Utc>,
+) -> Vec<ValidationIssue> {
+    use ValidationIssue::*;
+    let mut issues = Vec::new();
+
+    if query.columns().is_empty() {
+        issues.push(NoColumns);
+    }
+
+    let range = query.range().length_at(now);
+    let step = query.step().to_seconds();
+    if range == 0 {
+        issues.push(ZeroValue("time range".into()));
+    }
+    if step == 0 {
+        issues.push(ZeroValue("step".into()));
+    }
+    if let Some(bar) = query.resample() {
+        let bar_secs = bar.to_seconds();
+        if bar_secs == 0 {
+            issues.push(ZeroValue("resample bar".into()));
+        } else if bar_secs >= step || !step.is_multiple_of(bar_secs) {
+            issues.push(InvalidResample(bar.to_string()));
+        }
+    }
+    if range > 0 && step > 0 {
+        if step > range {
+            issues.push(StepExceedsRange(query.step().to_string()));
+        } else {
+            if !range.is_multiple_of(step) {
+                issues.push(PartialStep(query.step().to_string()));
+            }
+            // warm-up rows of window functions are computed too
+            let rows = query.rows_count(now).saturating_add(query.lookback());
+            if rows > MAX_ROWS {
+                issues.push(TooManyRows(rows, MAX_ROWS));
+            }
+        }
+    }
+
+    let columns = query.columns();
+    for (i, column) in columns.iter().enumerate() {
+        // reported once, at the second occurrence
+        let expr = column.expr();
+        if columns[..i].iter().filter(|c| c.expr() == expr).count() == 1 {
+            issues.push(DuplicateExpression(expr.to_string()));
+        }
+        if let Some(alias) = column.alias()
+            && columns[..i]
+                .iter()
+                .filter(|c| c.alias() == Some(alias))
+                .count()
+                == 1
+        {
+            issues.push(DuplicateAlias(alias.to_string()));
+        }
+    }
+
+    if let Some(SortKey::Alias(alias)) = query.order_by().map(|order_by| order_by.key())
+        && !columns.iter().any(|c| c.alias() == Some(alias.as_str()))
+    {
+        issues.push(UnknownAlias(alias.clone()));
+    }
+
+    issues.extend(unknown_metrics(query, catalog).into_iter().map(|sm| {
+        let name = sm.metric().name();
+        UnknownMetric(name.to_string(), catalog.suggest(name), sm.span())
+    }));
+    for expr in subexpressions(query) {
+        match expr {
+            Expr::Call(func, args) => {
+                if let Some(reason) = invalid_arguments(*func, args) {
+                    issues.push(InvalidCall(expr.to_string(), reason));
+                }
+            }
+            Expr::Binary(_, Operator::Div, right) if **right == Expr::Value(0.0) => {
+                issues.push(DivisionByZero(expr.to_string()));
+            }
+            _ => {}
+        }
+    }
+
+    issues.sort_by_key(|issue| !issue.is_error());
+    issues
+}
+
+/// Data of the metrics the query refers to which are not in the `catalog`,
+/// the first use of each.
+pub(crate) fn unknown_metrics<'a>(
+    query: &'a Query,
+    catalog: &MetricCatalog,
+) -> Vec<&'a SymbolMetric> {
+    let mut unknown: Vec<&SymbolMetric> = Vec::new();
+    for expr in subexpressions(query) {
+        if let Expr::Data(sm) = expr
+            && !catalog.contains(sm.metric())
+            && !unknown.iter().any(|other| other.metric() == sm.metric())
+        {
+            unknown.push(sm);
+        }
+    }
+    unknown
+}
+
+/// The expressions of the columns, the filter and ORDER BY, with the expressions
+/// they are built of.
+fn subexpressions(query: &Query) -> Vec<&Expr> {
+    let mut exprs = Vec::new();
+    let order_by = query.order_by().and_then(|order_by| order_by.expr());
+    for expr in query.expressions().chain(query.filter()).chain(order_by) {
+        collect_subexpressions(expr, &mut exprs);
+    }
+    exprs
+}
+
+/// What is wrong with the arguments of a call, checked as by the parser.
+fn invalid_arguments(func: Function, args: &[Expr]) -> Option<Cow<'static, str>> {
+    let arity = func.arity();
+    if !arity.contains(&args.len()) {
+        let reason = match arity.start() == arity.end() {
+            true => format!("{} expects {} argument(s)", func, arity.start()),
+            false => format!(
+                "{} expects {} to {} arguments",
+                func,
+                arity.start(),
+                arity.end()
+            ),
+        };
+        return Some(reason.into());
+    }
+    if func.takes_period() && func.period(args).is_none() {
+        let reason = format!(
+            "the period must be an integer from {} to {}",
+            func.min_period(),
+            MAX_ROWS
+        );
+        return Some(reason.into());
+    }
+    if func == Function::Percentile
+        && !matches!(args.last(), Some(Expr::Value(p)) if (0.0..=100.0).contains(p))
+    {
+        return Some("the percentile must be between 0 and 100".into());
+    }
+    None
+}
+
+/// The expression and all the expressions it is built of.
+pub(crate) fn collect_subexpressions<'a>(expr: &'a Expr, acc: &mut Vec<&'a Expr>) {
+    acc.push(expr);
+    match expr {
+        Expr::Binary(left, _, right) => {
+            collect_subexpressions(left, acc);
+            collect_subexpressions(right, acc);
+        }
+        Expr::Call(_, args) => args.iter().for_each(|arg| collect_subexpressions(arg, acc)),
+        Expr::Case(branches, default) => {
+            for (condition, value) in branches {
+                collect_subexpressions(condition, acc);
+                collect_subexpressions(value, acc);
+            }
+            if let Some(default) = default {
+                collect_subexpressions(default, acc);
+            }
+        }
+        Expr::Neg(expr) | Expr::Not(expr) => collect_subexpressions(expr, acc),
+        Expr::Data(_) | Expr::Value(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Metric, MetricInfo, Rollup, parse_query};
+
+    fn issues(src: &str) -> Vec<String> {
+        validate(&parse_query(src).unwrap(), MetricCatalog::well_known())
+            .iter()
+            .map(|issue| format!("{:?}: {}", issue.severity(), issue))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(issues("GET AAPL.close FOR LAST 30 days STEP 1 day").is_empty());
+        assert_eq!(
+            vec![
+                "Error: Zero value: time range must be positive",
+                "Error: Zero value: step must be positive",
+            ],
+            issues("GET AAPL.close FOR LAST 0 days STEP 0 days")
+        );
+        assert_eq!(
+            vec!["Error: Step 1 day is longer than the time range"],
+            issues("GET AAPL.close FOR LAST 1 hour STEP 1 day")
+        );
+        assert_eq!(
+            vec![
+                "Warning: Time range is not a multiple of the step 3 hours, its remainder is not covered by a row"
+            ],
+            issues("GET AAPL.close FOR LAST 10 hours STEP 3 hours")
+        );
+        assert_eq!(
+            vec![
+                "Error: Query computes 525600 rows including the warm-up, more than the maximum of 100000"
+            ],
+            issues("GET AAPL.close FOR LAST 365 days STEP 1 minute")
+        );
+
+        // a month is as long as the calendar makes it when the query runs
+        let query = parse_query("GET AAPL.close FOR LAST 1 month STEP 7 days").unwrap();
+        let codes = |now: &str| -> Vec<&str> {
+            validate_at(&query, MetricCatalog::well_known(), now.parse().unwrap())
+                .iter()
+                .map(ValidationIssue::code)
+                .collect()
+        };
+        assert!(codes("2025-03-15T00:00:00Z").is_empty());
+        assert_eq!(vec!["partial_step"], codes("2025-03-31T00:00:00Z"));
+        assert_eq!(
+            vec![
+                "Error: Query computes 100029 rows including the warm-up, more than the maximum of 100000"
+            ],
+            issues("GET AAPL.close FOR LAST 30 days STEP 1 day WHERE SMA(AAPL.close, 100000) > 1")
+        );
+        assert_eq!(
+            vec!["Warning: Expression AAPL.close + 1 is selected more than once"],
+            issues(
+                "GET AAPL.close + 1 AS a, AAPL.close + 1 AS b, AAPL.close + 1 FOR LAST 30 days STEP 1 day"
+            )
+        );
+        assert_eq!(
+            vec![
+                "Warning: Alias c is given to more than one column",
+                "Warning: Expression AAPL.close is selected more than once",
+            ],
+            issues(
+                "GET AAPL.close AS c, MSFT.close AS c, AAPL.close, SPY.close AS c FOR LAST 1 day STEP 1 hour"
+            )
+        );
+        assert!(issues("GET AAPL.close, XNAS:AAPL.close FOR LAST 1 day STEP 1 hour").is_empty());
+        assert_eq!(
+            vec![
+                "Warning: Division by zero in AAPL.close / 0",
+                "Warning: Division by zero in MSFT.close / 0",
+            ],
+            issues(
+                "GET SMA(AAPL.close / 0, 5) FOR LAST 30 days STEP 1 day WHERE MSFT.close / 0 > 1"
+            )
+        );
+    }
+
+    #[test]
+    fn test_validate_metrics() {
+        assert_eq!(
+            vec![
+                "Error: Unknown metric 'clsoe', did you mean 'close'?",
+                "Error: Unknown metric 'vwap'",
+            ],
+            issues("GET AAPL.clsoe, MSFT.clsoe FOR LAST 1 day STEP 1 hour WHERE AAPL.vwap > 1")
+        );
+
+        let catalog = MetricCatalog::default().with(
+            Metric::new("vwap"),
+            MetricInfo::new("currency", Rollup::VolumeWeighted),
+        );
+        let query = parse_query("GET AAPL.vwap FOR LAST 1 day STEP 1 hour").unwrap();
+        assert!(validate(&query, &catalog).is_empty());
+        assert_eq!(
+            vec![ValidationIssue::UnknownMetric(
+                "vwap".into(),
+                vec![],
+                Some(4..13)
+            )],
+            validate(
+                &query,
+                &MetricCatalog::empty()
+                    .with(Metric::CLOSE, MetricInfo::new("currency", Rollup::Last))
+            )
+        );
+    }
+}
@@ -9,17 +9,22 @@ pub struct QueryRoot;
 
 #[Object]
 impl QueryRoot {
+    // each argument of the GraphQL field is a parameter
+    #[allow(clippy::too_many_arguments)]
     async fn get_metrics(
         &self,
         ctx: &async_graphql::Context<'_>,
         symbol: String,
+        exchange: Option<String>,
         metrics: Vec<String>,
         from: String,
         to: String,
         step: String,
     ) -> Result<Vec<MetricRecord>, MetricsApiError> {
         let service = ctx.data::<Arc<MetricsService>>().unwrap();
-        service.get_metrics_for_symbol(symbol, metrics, from, to, step).await
+        service
+            .get_metrics_for_symbol(symbol, exchange, metrics, from, to, step)
+            .await
     }
 }
 
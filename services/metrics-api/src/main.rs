@@ -15,7 +15,6 @@ use std::{
     sync::Arc,
 };
 use tokio::net::TcpListener;
-use toml;
 
 use crate::{
     api::{
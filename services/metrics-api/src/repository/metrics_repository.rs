@@ -5,6 +5,7 @@ pub trait MetricsRepository: Send + Sync {
     async fn get_metrics(
         &self,
         symbol: String,
+        exchange: Option<String>,
         metrics: Vec<String>,
         from: String,
         to: String,
@@ -4,6 +4,7 @@ use crate::{
     error::MetricsApiError, repository::MetricsRepository, shared::{MetricRecord, MetricValue}
 };
 
+#[derive(Default)]
 pub struct MetricsRepositoryMock {}
 
 impl MetricsRepositoryMock {
@@ -27,12 +28,13 @@ impl MetricsRepository for MetricsRepositoryMock {
     async fn get_metrics(
         &self,
         _symbol: String,
+        _exchange: Option<String>,
         metrics: Vec<String>,
         from: String,
         to: String,
         step: String,
     ) -> Result<Vec<MetricRecord>, MetricsApiError> {
-        let step_hours: u64 = step.parse().unwrap_or(1);
+        let step_secs: u64 = step.parse().unwrap_or(3600).max(1);
         let from = DateTime::parse_from_rfc3339(&from)
             .unwrap()
             .with_timezone(&Utc);
@@ -54,7 +56,7 @@ impl MetricsRepository for MetricsRepositoryMock {
                 .collect();
 
             records.push(MetricRecord { timestamp, values });
-            current += chrono::Duration::hours(step_hours as i64);
+            current += chrono::Duration::seconds(step_secs as i64);
         }
 
         Ok(records)
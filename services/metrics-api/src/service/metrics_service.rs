@@ -13,13 +13,14 @@ impl MetricsService {
     pub async fn get_metrics_for_symbol(
         &self,
         symbol: String,
+        exchange: Option<String>,
         metrics: Vec<String>,
         from: String,
         to: String,
         step: String,
     ) -> Result<Vec<MetricRecord>, MetricsApiError> {
         self.metrics_repo
-            .get_metrics(symbol, metrics, from, to, step)
+            .get_metrics(symbol, exchange, metrics, from, to, step)
             .await
     }
 }
@@ -1,5 +1,7 @@
 mod query_handler;
 mod root_handler;
+mod universes_handler;
 
 pub use query_handler::*;
 pub use root_handler::*;
+pub use universes_handler::*;
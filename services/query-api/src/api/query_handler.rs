@@ -3,36 +3,163 @@ use axum::{
     http::{StatusCode, header},
     response::{IntoResponse, Response},
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
 
 use crate::{domain::Table, error::AppError, service::QueryService};
 use common::shared::StatusMsg;
-use query_parser::parse_query;
+use query_parser::{ParseError, Query, ValidationIssue, parse_query, parse_query_recovering};
 
 #[derive(Deserialize)]
 pub struct QueryReq {
-    query: String,
+    #[serde(flatten)]
+    source: QuerySource,
     #[serde(default)]
     format: OutputFormat,
 }
 
+/// The query to run: either its source (`"query": "GET ..."`) or the parsed
+/// query, i.e. built or stored by another service (`"ast": {...}`).
 #[derive(Deserialize)]
 #[serde(rename_all = "lowercase")]
+pub enum QuerySource {
+    Query(String),
+    Ast(Box<Query>),
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
 pub enum OutputFormat {
     Json,
+    #[default]
     Text,
 }
 
-impl Default for OutputFormat {
-    fn default() -> Self {
-        OutputFormat::Text
+/// Error message for a query that could not be parsed: the first error found, with the others.
+#[derive(Serialize)]
+pub struct ParseErrorsMsg {
+    status: &'static str,
+    #[serde(flatten)]
+    first: ParseErrorMsg,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    others: Vec<ParseErrorMsg>,
+}
+
+impl ParseErrorsMsg {
+    fn new(errors: &[ParseError]) -> Self {
+        let mut errors = errors.iter().map(ParseErrorMsg::from);
+        Self {
+            status: "error",
+            first: errors.next().unwrap_or_default(),
+            others: errors.collect(),
+        }
+    }
+}
+
+/// Parse error with the position of the problem.
+#[derive(Serialize, Default)]
+pub struct ParseErrorMsg {
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    span: Option<Range<usize>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    expected: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    suggestions: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snippet: Option<String>,
+}
+
+impl From<&ParseError> for ParseErrorMsg {
+    fn from(err: &ParseError) -> Self {
+        Self {
+            message: err.to_string(),
+            span: err.span(),
+            line: err.line_col().map(|(line, _)| line),
+            column: err.line_col().map(|(_, column)| column),
+            token: err.token().map(str::to_string),
+            expected: err.expected().to_vec(),
+            suggestions: err.suggestions().to_vec(),
+            snippet: err.snippet().map(str::to_string),
+        }
+    }
+}
+
+/// Issue found by validating a query.
+#[derive(Serialize)]
+pub struct IssueMsg {
+    code: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    span: Option<Range<usize>>,
+}
+
+impl From<&ValidationIssue> for IssueMsg {
+    fn from(issue: &ValidationIssue) -> Self {
+        Self {
+            code: issue.code(),
+            message: issue.to_string(),
+            span: issue.span(),
+        }
+    }
+}
+
+/// Error message for a query refused by validation, with all the issues found.
+#[derive(Serialize)]
+pub struct ValidationErrorMsg {
+    status: &'static str,
+    message: String,
+    errors: Vec<IssueMsg>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<IssueMsg>,
+}
+
+impl ValidationErrorMsg {
+    fn new(message: String, issues: &[ValidationIssue]) -> Self {
+        let (errors, warnings) = issues.iter().partition(|issue| issue.is_error());
+        Self {
+            status: "error",
+            message,
+            errors: issue_msgs(errors),
+            warnings: issue_msgs(warnings),
+        }
     }
 }
 
+/// Result table with the warnings of the query.
+#[derive(Serialize)]
+pub struct QueryResultMsg {
+    #[serde(flatten)]
+    table: Table,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<IssueMsg>,
+}
+
+fn issue_msgs(issues: Vec<&ValidationIssue>) -> Vec<IssueMsg> {
+    issues.into_iter().map(IssueMsg::from).collect()
+}
+
+/// Warnings as trailing lines of a text response.
+fn warning_lines(issues: &[ValidationIssue]) -> String {
+    issues
+        .iter()
+        .filter(|issue| !issue.is_error())
+        .map(|issue| format!("\nWarning: {issue}"))
+        .collect()
+}
+
 pub enum QueryResultResponse {
-    OkJson(Json<Table>),
+    OkJson(Json<QueryResultMsg>),
     OkText(String),
     ErrorJson(StatusCode, Json<StatusMsg>),
+    ParseErrorJson(StatusCode, Json<ParseErrorsMsg>),
+    ValidationErrorJson(StatusCode, Json<ValidationErrorMsg>),
     ErrorText(StatusCode, String),
 }
 
@@ -43,6 +170,8 @@ impl IntoResponse for QueryResultResponse {
             OkJson(table) => table.into_response(),
             OkText(txt) => ([(header::CONTENT_TYPE, "text/plain")], txt).into_response(),
             ErrorJson(code, err) => (code, err).into_response(),
+            ParseErrorJson(code, err) => (code, err).into_response(),
+            ValidationErrorJson(code, err) => (code, err).into_response(),
             ErrorText(code, msg) => {
                 (code, [(header::CONTENT_TYPE, "text/plain")], msg).into_response()
             }
@@ -55,31 +184,69 @@ pub async fn query_handler(
     Json(req): Json<QueryReq>,
 ) -> impl IntoResponse {
     use QueryResultResponse::*;
-    let result = execute_query(&req.query, &service).await;
+    let result = execute_query(req.source, &service).await;
 
     match (result, req.format) {
-        (Ok(table), OutputFormat::Text) => OkText(table.to_string()).into_response(),
+        (Ok((table, warnings)), OutputFormat::Text) => {
+            OkText(format!("{table}{}", warning_lines(&warnings))).into_response()
+        }
 
-        (Ok(table), OutputFormat::Json) => OkJson(Json(table)).into_response(),
+        (Ok((table, warnings)), OutputFormat::Json) => {
+            let warnings = issue_msgs(warnings.iter().collect());
+            OkJson(Json(QueryResultMsg { table, warnings })).into_response()
+        }
 
         (Err(err), format) => {
             let status = match err {
-                AppError::ParseError(_) => StatusCode::BAD_REQUEST,
+                AppError::ParseError(_) | AppError::ValidationError(_) => StatusCode::BAD_REQUEST,
                 AppError::GQLError(_) | AppError::NetworkError(_) => StatusCode::BAD_GATEWAY,
                 _ => StatusCode::INTERNAL_SERVER_ERROR,
             };
             let message = err.to_string();
-            let body = match format {
-                OutputFormat::Text => ErrorText(status, message),
-                OutputFormat::Json => ErrorJson(status, Json(StatusMsg::error(message))),
+            let body = match (format, &err) {
+                (OutputFormat::Text, AppError::ParseError(errors)) => {
+                    let lines: Vec<String> = errors
+                        .iter()
+                        .map(|err| {
+                            let snippet = err.snippet().unwrap_or_default();
+                            format!("Query parse error: {err}\n{snippet}")
+                        })
+                        .collect();
+                    ErrorText(status, lines.join("\n"))
+                }
+                (OutputFormat::Text, AppError::ValidationError(issues)) => {
+                    ErrorText(status, format!("{message}{}", warning_lines(issues)))
+                }
+                (OutputFormat::Text, _) => ErrorText(status, message),
+                (OutputFormat::Json, AppError::ParseError(errors)) => {
+                    ParseErrorJson(status, Json(ParseErrorsMsg::new(errors)))
+                }
+                (OutputFormat::Json, AppError::ValidationError(issues)) => {
+                    ValidationErrorJson(status, Json(ValidationErrorMsg::new(message, issues)))
+                }
+                (OutputFormat::Json, _) => ErrorJson(status, Json(StatusMsg::error(message))),
             };
             (status, body).into_response()
         }
     }
 }
 
-async fn execute_query(query_str: &str, service: &QueryService) -> Result<Table, AppError> {
-    let parsed_query = parse_query(query_str)?;
-    let table = service.run_query(&parsed_query).await?;
-    Ok(table)
+/// Parses the query, unless given parsed, and runs it with the service. A query which
+/// does not parse is reported with all its errors, metrics checked against the catalog.
+async fn execute_query(
+    source: QuerySource,
+    service: &QueryService,
+) -> Result<(Table, Vec<ValidationIssue>), AppError> {
+    let parsed_query = match source {
+        QuerySource::Query(query_str) => parse_query(&query_str).map_err(|err| {
+            // every error of the query, rather than the first one
+            let (_, errors) = parse_query_recovering(&query_str, service.catalog()).into_parts();
+            AppError::ParseError(match errors.is_empty() {
+                true => vec![err],
+                false => errors,
+            })
+        })?,
+        QuerySource::Ast(query) => *query,
+    };
+    service.run_query(&parsed_query).await
 }
@@ -0,0 +1,17 @@
+use std::collections::BTreeMap;
+
+use axum::{Extension, Json, response::IntoResponse};
+use serde::Serialize;
+
+use crate::service::QueryService;
+
+/// The universes queries may refer to (`@name`), with their symbols.
+#[derive(Serialize)]
+pub struct UniversesMsg<'a> {
+    universes: BTreeMap<&'a str, &'a [String]>,
+}
+
+pub async fn universes_handler(Extension(service): Extension<QueryService>) -> impl IntoResponse {
+    let universes = service.universes().iter().collect();
+    Json(UniversesMsg { universes }).into_response()
+}
@@ -1,26 +1,146 @@
 use std::fmt;
-use serde::Serialize;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Serialize, Serializer, ser::SerializeStruct};
 
 use crate::shared::MAX_HEADER_WIDTH;
 
-#[derive(Debug, Serialize)]
+/// Result of a query: rows of values, each with the start time of its step, which
+/// is the first column. Columns computed from conditions (see `Expr::is_boolean`)
+/// hold 1 or 0 and are rendered as `true`/`false`, in text and JSON.
+#[derive(Debug)]
 pub struct Table {
     headers: Vec<String>,
+    times: Vec<DateTime<Utc>>,
     rows: Vec<Vec<f32>>,
+    boolean_columns: Vec<bool>,
+    summary: Vec<SummaryRow>,
+}
+
+/// Single value of a row, as rendered.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum Cell {
+    /// Start time of the row, in RFC 3339
+    Time(String),
+    Number(f32),
+    /// `None` for `NaN`
+    Boolean(Option<bool>),
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cell::Time(time) => write!(f, "{}", time),
+            Cell::Number(val) => write!(f, "{:.2}", val),
+            Cell::Boolean(Some(val)) => write!(f, "{}", val),
+            Cell::Boolean(None) => write!(f, "NaN"),
+        }
+    }
+}
+
+/// Statistic computed over a whole column (i.e. mean), one value per column of values
+/// (the time column is not included).
+#[derive(Debug, Serialize)]
+pub struct SummaryRow {
+    label: String,
+    values: Vec<f32>,
+}
+
+impl SummaryRow {
+    pub fn new(label: &str, values: Vec<f32>) -> Self {
+        Self {
+            label: label.to_string(),
+            values,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
 }
 
 impl Table {
-    pub fn new(headers: Vec<String>, rows: Vec<Vec<f32>>) -> Self {
-        Self { headers, rows }
+    /// Table of the `rows` of values, each starting at the time of the same index.
+    /// The `headers` name the time column and the columns of values.
+    pub fn new(headers: Vec<String>, times: Vec<DateTime<Utc>>, rows: Vec<Vec<f32>>) -> Self {
+        Self {
+            headers,
+            times,
+            rows,
+            boolean_columns: Vec::new(),
+            summary: Vec::new(),
+        }
+    }
+
+    /// Marks columns of values (the time column is not included) holding booleans.
+    pub fn with_boolean_columns(mut self, boolean_columns: Vec<bool>) -> Self {
+        self.boolean_columns = boolean_columns;
+        self
+    }
+
+    pub fn with_summary(mut self, summary: Vec<SummaryRow>) -> Self {
+        self.summary = summary;
+        self
     }
 
     pub fn headers(&self) -> impl Iterator<Item = &String> {
         self.headers.iter()
     }
 
+    /// Start time of each row.
+    pub fn times(&self) -> impl Iterator<Item = &DateTime<Utc>> {
+        self.times.iter()
+    }
+
+    /// Values of each row, without the time.
     pub fn rows(&self) -> impl Iterator<Item = &Vec<f32>> {
         self.rows.iter()
     }
+
+    pub fn summary(&self) -> impl Iterator<Item = &SummaryRow> {
+        self.summary.iter()
+    }
+
+    /// Value of the `col`-th column of values of a row.
+    fn cell(&self, col: usize, value: f32) -> Cell {
+        let boolean = self.boolean_columns.get(col).copied().unwrap_or(false);
+        match boolean {
+            true if value.is_nan() => Cell::Boolean(None),
+            true => Cell::Boolean(Some(value != 0.0)),
+            false => Cell::Number(value),
+        }
+    }
+
+    /// Cells of the `row`-th row, the time first.
+    fn cells(&self, row: usize) -> Vec<Cell> {
+        let time = self.times.get(row).map_or_else(String::new, |time| {
+            time.to_rfc3339_opts(SecondsFormat::Secs, true)
+        });
+        let values = self.rows[row].iter().enumerate();
+        std::iter::once(Cell::Time(time))
+            .chain(values.map(|(i, value)| self.cell(i, *value)))
+            .collect()
+    }
+}
+
+impl Serialize for Table {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let rows: Vec<Vec<Cell>> = (0..self.rows.len()).map(|row| self.cells(row)).collect();
+
+        let mut state = serializer.serialize_struct("Table", 3)?;
+        state.serialize_field("headers", &self.headers)?;
+        state.serialize_field("rows", &rows)?;
+        if self.summary.is_empty() {
+            state.skip_field("summary")?;
+        } else {
+            state.serialize_field("summary", &self.summary)?;
+        }
+        state.end()
+    }
 }
 
 impl fmt::Display for Table {
@@ -30,17 +150,31 @@ impl fmt::Display for Table {
             .map(|h| shorten_name(h, MAX_HEADER_WIDTH))
             .collect();
 
-        let mut col_widths: Vec<usize> = display_headers.iter().map(|h| h.len()).collect();
+        let mut col_widths: Vec<usize> =
+            display_headers.iter().map(|h| h.chars().count()).collect();
 
-        for row in &self.rows {
-            for (i, val) in row.iter().enumerate() {
-                let len = format!("{:.2}", val).len();
+        let rows: Vec<Vec<String>> = (0..self.rows.len())
+            .map(|row| self.cells(row).iter().map(Cell::to_string).collect())
+            .collect();
+        for row in &rows {
+            for (i, cell) in row.iter().enumerate() {
+                let len = cell.chars().count();
                 if len > col_widths[i] {
                     col_widths[i] = len;
                 }
             }
         }
 
+        for row in &self.summary {
+            col_widths[0] = col_widths[0].max(row.label.chars().count());
+            for (i, val) in row.values.iter().enumerate() {
+                let len = format!("{:.2}", val).len();
+                if len > col_widths[i + 1] {
+                    col_widths[i + 1] = len;
+                }
+            }
+        }
+
         for (i, header) in display_headers.iter().enumerate() {
             write!(f, "{:>width$}  ", header, width = col_widths[i])?;
         }
@@ -51,27 +185,82 @@ impl fmt::Display for Table {
         }
         writeln!(f)?;
 
-        for row in &self.rows {
-            for (i, value) in row.iter().enumerate() {
-                if i == 0 {
-                    write!(f, "{:>width$} ", *value as u32, width=col_widths[0])?;
-                } else {
-                    write!(f, "{:>width$.2} ", value, width=col_widths[i])?;
-                }
+        for row in &rows {
+            for (i, cell) in row.iter().enumerate() {
+                write!(f, "{:>width$} ", cell, width=col_widths[i])?;
+            }
+            writeln!(f)?;
+        }
+
+        if !self.summary.is_empty() {
+            for width in &col_widths {
+                write!(f, "{:-<width$}--", "", width = *width)?;
             }
             writeln!(f)?;
+
+            for row in &self.summary {
+                write!(f, "{:>width$} ", row.label, width = col_widths[0])?;
+                for (i, value) in row.values.iter().enumerate() {
+                    write!(f, "{:>width$.2} ", value, width = col_widths[i + 1])?;
+                }
+                writeln!(f)?;
+            }
         }
 
         Ok(())
     }
 }
 
+/// Cuts the name to at most `max` characters, ending in `...` if shortened.
 fn shorten_name(s: &str, max: usize) -> String {
-    if s.len() <= max {
+    if s.chars().count() <= max {
         s.to_string()
     } else if max > 3 {
-        format!("{}...", &s[..max - 3])
+        format!("{}...", s.chars().take(max - 3).collect::<String>())
     } else {
-        s[..max].to_string()
+        s.chars().take(max).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hours(count: u32) -> Vec<DateTime<Utc>> {
+        let from: DateTime<Utc> = "2025-06-02T00:00:00Z".parse().unwrap();
+        (0..count).map(|i| from + chrono::TimeDelta::hours(i.into())).collect()
+    }
+
+    #[test]
+    fn test_boolean_columns() {
+        let table = Table::new(
+            vec!["time".into(), "close".into(), "up".into()],
+            hours(3),
+            vec![vec![1.5, 1.0], vec![2.0, 0.0], vec![1.0, f32::NAN]],
+        )
+        .with_boolean_columns(vec![false, true]);
+
+        let json = serde_json::to_string(&table).unwrap();
+        assert_eq!(
+            r#"{"headers":["time","close","up"],"rows":[["2025-06-02T00:00:00Z",1.5,true],["2025-06-02T01:00:00Z",2.0,false],["2025-06-02T02:00:00Z",1.0,null]]}"#,
+            json
+        );
+
+        let text = table.to_string();
+        assert!(text.contains("2025-06-02T00:00:00Z  1.50  true"));
+        assert!(text.contains("2.00 false"));
+    }
+
+    #[test]
+    fn test_non_ascii_headers() {
+        assert_eq!("\"ÄÄÄÄ\"....", shorten_name("\"ÄÄÄÄ\".close", 10));
+        assert_eq!("ÄÄÄ", shorten_name("ÄÄÄÄ", 3));
+
+        let header = query_parser::parse_expr(r#""ÄÄÄÄ".close"#).unwrap().to_string();
+        let table = Table::new(vec!["time".into(), header], hours(1), vec![vec![1.5]]);
+        let text = table.to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!("                time  \"ÄÄÄÄ\"....  ", lines[0]);
+        assert_eq!(lines[0].chars().count(), lines[1].chars().count());
     }
 }
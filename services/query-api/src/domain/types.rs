@@ -1,5 +1,40 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::Index};
+use chrono::{DateTime, Utc};
 use query_parser::Metric;
 
-pub type MetricData = HashMap<Metric, Vec<f32>>;
+/// Time series of a symbol: the start time of each bar and the values of each metric,
+/// one per bar.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricData {
+    timestamps: Vec<DateTime<Utc>>,
+    values: HashMap<Metric, Vec<f32>>,
+}
+
+impl MetricData {
+    pub fn new(timestamps: Vec<DateTime<Utc>>, values: HashMap<Metric, Vec<f32>>) -> Self {
+        Self { timestamps, values }
+    }
+
+    pub fn timestamps(&self) -> &[DateTime<Utc>] {
+        &self.timestamps
+    }
+
+    pub fn get(&self, metric: &Metric) -> Option<&Vec<f32>> {
+        self.values.get(metric)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Metric, &Vec<f32>)> {
+        self.values.iter()
+    }
+}
+
+impl Index<&Metric> for MetricData {
+    type Output = Vec<f32>;
+
+    fn index(&self, metric: &Metric) -> &Self::Output {
+        &self.values[metric]
+    }
+}
+
+/// Data of each symbol, by its qualified symbol (`AAPL` or `XNAS:AAPL`).
 pub type SymbolData = HashMap<String, MetricData>;
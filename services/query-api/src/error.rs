@@ -1,13 +1,18 @@
 use reqwest;
 use thiserror::Error;
-use query_parser;
+use query_parser::{self, ParseError, ValidationIssue};
 
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum AppError {
 
-    #[error("Query parse error: {0}")]
-    ParseError(#[from] query_parser::ParseError),
+    /// Errors of a query that could not be parsed, in the order of the source.
+    #[error("Query parse error: {}", parse_errors_message(.0))]
+    ParseError(Vec<query_parser::ParseError>),
+
+    /// Issues found by validating the query, at least one of them an error.
+    #[error("Invalid query: {}", ValidationIssue::errors_message(.0))]
+    ValidationError(Vec<ValidationIssue>),
 
     #[error("GraphQL error: {0}")]
     GQLError(String),
@@ -24,3 +29,8 @@ impl From<reqwest::Error> for AppError {
         AppError::NetworkError(err.to_string())
     }
 }
+
+fn parse_errors_message(errors: &[ParseError]) -> String {
+    let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+    messages.join("; ")
+}
@@ -11,12 +11,17 @@ use std::{
     sync::Arc,
 };
 
-use axum::{Extension, Router, routing::post};
+use axum::{
+    Extension, Router,
+    routing::{get, post},
+};
 use tokio::net::TcpListener;
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::EnvFilter;
 
+use query_parser::MetricCatalog;
+
 use crate::{repository::MetricsRepositoryGql, service::QueryService, shared::Config};
 
 #[tokio::main]
@@ -28,11 +33,14 @@ async fn main() -> Result<(), anyhow::Error> {
     let config = load_config()?;
 
     let metrics_repo = MetricsRepositoryGql::new(&config.graphql_server);
-    let query_srv = QueryService::new(Arc::new(metrics_repo));
+    let mut catalog = MetricCatalog::default();
+    catalog.extend(config.metrics);
+    let query_srv = QueryService::new(Arc::new(metrics_repo), catalog, config.universes);
 
     let app = Router::new()
-        .route("/", axum::routing::get(api::root_handler))
+        .route("/", get(api::root_handler))
         .route("/query", post(api::query_handler))
+        .route("/universes", get(api::universes_handler))
         .layer(Extension(query_srv));
 
     let listener = TcpListener::bind(config.query_server).await?;
@@ -1,5 +1,5 @@
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use futures::future::try_join_all;
 use graphql_client::GraphQLQuery;
 use query_parser::Metric;
@@ -38,6 +38,7 @@ impl MetricsRepositoryGql {
 
     /// Constructs GraphQL query variables for a given data target and time window.
     /// The variables are required to intiialize GraphQL client.
+    /// The step is sent in seconds.
     fn build_query_vars(
         &self,
         target: &TargetMetrics,
@@ -49,10 +50,11 @@ impl MetricsRepositoryGql {
 
         get_metrics::Variables {
             symbol: target.symbol().into(),
+            exchange: target.exchange().map(str::to_string),
             metrics: target.metrics().map(|m| m.to_string()).collect(),
             from: from_utc.to_rfc3339(),
             to: to_utc.to_rfc3339(),
-            step: (step.as_secs() / 3600).to_string(),
+            step: step.as_secs().to_string(),
         }
     }
 
@@ -86,17 +88,27 @@ impl MetricsRepositoryGql {
 
     /// Converts raw GraphQL response data into a map of metrics to float time series.
     /// Expects the response to be grouped by timestamp, and flattens it into metric-centric form.
+    /// Timestamps are RFC 3339, or UTC without an offset (`2025-06-02T09:30:00`).
     fn transform_response(&self, data: get_metrics::ResponseData) -> Result<MetricData, AppError> {
-        let mut result: MetricData = HashMap::new();
+        let mut timestamps = Vec::with_capacity(data.get_metrics.len());
+        let mut result: HashMap<Metric, Vec<f32>> = HashMap::new();
 
         for record in data.get_metrics {
+            let timestamp = DateTime::parse_from_rfc3339(&record.timestamp)
+                .map(|time| time.to_utc())
+                .or_else(|_| {
+                    NaiveDateTime::parse_from_str(&record.timestamp, "%Y-%m-%dT%H:%M:%S")
+                        .map(|time| time.and_utc())
+                })
+                .map_err(|_| GQLError(format!("Invalid timestamp: {}", record.timestamp)))?;
+            timestamps.push(timestamp);
             for value in record.values {
-                let metric = Metric::try_from(value.metric.as_str())?;
+                let metric = Metric::new(value.metric);
                 result.entry(metric).or_default().push(value.value as f32);
             }
         }
 
-        Ok(result)
+        Ok(MetricData::new(timestamps, result))
     }
 }
 
@@ -113,7 +125,7 @@ impl MetricsRepository for MetricsRepositoryGql {
     }
 
     async fn get_metrics_for_query_plan(&self, plan: &QueryPlan) -> Result<SymbolData, AppError> {
-        let range = DateRange::from_now(plan.range());
+        let range = plan.range();
 
         let futures = plan.targets().map(|target| {
             let vars = self.build_query_vars(target, &range, plan.step());
@@ -123,7 +135,7 @@ impl MetricsRepository for MetricsRepositoryGql {
         let data = try_join_all(futures).await?;
         Ok(plan
             .targets()
-            .map(|t| t.symbol().to_string())
+            .map(|t| t.qualified_symbol())
             .zip(data.into_iter())
             .collect())
     }
@@ -0,0 +1,375 @@
+use query_parser::{Expr, Function, MAX_ROWS};
+
+use crate::{domain::SummaryRow, error::AppError};
+
+/// Reduces a window (or a whole series) of values to a single value.
+type Reducer = fn(&[f32]) -> f32;
+
+/// Rows a column is computed for. The first `warmup` rows are history preceding
+/// the query range; aggregates reduce only the selected rows, which are in the range
+/// and meet the WHERE condition.
+#[derive(Debug, Clone)]
+pub(crate) struct Rows {
+    warmup: usize,
+    selected: Vec<bool>,
+}
+
+impl Rows {
+    pub(crate) fn new(size: usize, warmup: usize) -> Self {
+        let selected = (0..size).map(|i| i >= warmup).collect();
+        Self { warmup, selected }
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.selected.len()
+    }
+
+    pub(crate) fn warmup(&self) -> usize {
+        self.warmup
+    }
+
+    /// Keeps selected only the rows for which the mask is true.
+    pub(crate) fn filtered(mut self, mask: &[bool]) -> Self {
+        let mut mask = mask.iter();
+        for selected in self.selected.iter_mut() {
+            *selected &= mask.next().copied().unwrap_or(false);
+        }
+        self
+    }
+
+    /// Values of the selected rows, without NaN values.
+    fn select(&self, values: &[f32]) -> Vec<f32> {
+        values
+            .iter()
+            .zip(&self.selected)
+            .filter(|(v, selected)| **selected && !v.is_nan())
+            .map(|(v, _)| *v)
+            .collect()
+    }
+}
+
+/// Applies a built-in function to its arguments. Series arguments are computed
+/// with `eval`, so all of them have the same length (one value per time step).
+/// Period arguments (i.e. window size) are taken directly from the literal.
+/// Cumulative functions start accumulating after the warm-up `rows` and aggregates
+/// reduce the selected rows only.
+pub(crate) fn apply_function<F>(
+    func: Function,
+    args: &[Expr],
+    rows: &Rows,
+    eval: F,
+) -> Result<Vec<f32>, AppError>
+where
+    F: Fn(&Expr) -> Result<Vec<f32>, AppError>,
+{
+    use Function::*;
+    if !func.arity().contains(&args.len()) {
+        return Err(AppError::DataError(format!(
+            "Function {func} does not accept {} argument(s)",
+            args.len()
+        )));
+    }
+
+    let period = func.period(args);
+    if func.takes_period() && period.is_none() {
+        return Err(AppError::DataError(format!(
+            "Function {func} requires an integer period from {} to {MAX_ROWS}",
+            func.min_period()
+        )));
+    }
+    let period = period.unwrap_or(1);
+
+    let percentile = match (func, args.last()) {
+        (Percentile, Some(Expr::Value(p))) => *p as f32,
+        _ => f32::NAN,
+    };
+
+    let literals = (func.takes_period() || func == Percentile) as usize;
+    let series = args.iter().take(args.len() - literals);
+    let mut cols = series.map(eval).collect::<Result<Vec<_>, _>>()?.into_iter();
+
+    let col = match func {
+        Abs => unary(cols.next(), f32::abs),
+        Sqrt => unary(cols.next(), f32::sqrt),
+        Ln => unary(cols.next(), f32::ln),
+        Log10 => unary(cols.next(), f32::log10),
+        Exp => unary(cols.next(), f32::exp),
+        Round => unary(cols.next(), f32::round),
+        Floor => unary(cols.next(), f32::floor),
+        Ceil => unary(cols.next(), f32::ceil),
+        Pow => binary(cols.next(), cols.next(), f32::powf),
+        Min if args.len() == 1 => aggregate(cols.next(), rows, min),
+        Max if args.len() == 1 => aggregate(cols.next(), rows, max),
+        Min => binary(cols.next(), cols.next(), f32::min),
+        Max => binary(cols.next(), cols.next(), f32::max),
+        Sma => rolling(cols.next(), period, mean),
+        Stddev => rolling(cols.next(), period, stddev),
+        RollingMax => rolling(cols.next(), period, max),
+        RollingMin => rolling(cols.next(), period, min),
+        Ema => ema(cols.next(), period),
+        Diff => with_previous(cols.next(), |cur, prev| cur - prev),
+        PctChange => with_previous(cols.next(), |cur, prev| (cur - prev) / prev),
+        Lag => shift(cols.next(), period as isize),
+        Lead => shift(cols.next(), -(period as isize)),
+        Cumsum => cumulative(cols.next(), rows.warmup(), 0.0, |acc, v| acc + v),
+        Cumprod => cumulative(cols.next(), rows.warmup(), 1.0, |acc, v| acc * v),
+        Avg => aggregate(cols.next(), rows, mean),
+        Sum => aggregate(cols.next(), rows, |v| v.iter().sum()),
+        Count => aggregate(cols.next(), rows, |v| v.len() as f32),
+        Median => aggregate(cols.next(), rows, |v| quantile(v, 0.5)),
+        Percentile => aggregate(cols.next(), rows, |v| quantile(v, percentile / 100.0)),
+        other => {
+            return Err(AppError::DataError(format!(
+                "Function {other} is not supported"
+            )));
+        }
+    };
+    Ok(col)
+}
+
+fn unary(arg: Option<Vec<f32>>, f: fn(f32) -> f32) -> Vec<f32> {
+    arg.unwrap_or_default().into_iter().map(f).collect()
+}
+
+fn binary(left: Option<Vec<f32>>, right: Option<Vec<f32>>, f: fn(f32, f32) -> f32) -> Vec<f32> {
+    left.unwrap_or_default()
+        .into_iter()
+        .zip(right.unwrap_or_default())
+        .map(|(a, b)| f(a, b))
+        .collect()
+}
+
+/// Applies `f` to each window of `period` consecutive values ending at the current row.
+/// Rows without a full window (the first `period - 1`) are NaN.
+fn rolling(arg: Option<Vec<f32>>, period: usize, f: Reducer) -> Vec<f32> {
+    let arg = arg.unwrap_or_default();
+    (0..arg.len())
+        .map(|i| match (i + 1).checked_sub(period) {
+            Some(start) => f(&arg[start..=i]),
+            None => f32::NAN,
+        })
+        .collect()
+}
+
+/// Reduces the selected rows of the series (excluding NaN values) to a single value,
+/// repeated for every row.
+fn aggregate(arg: Option<Vec<f32>>, rows: &Rows, f: impl Fn(&[f32]) -> f32) -> Vec<f32> {
+    let arg = arg.unwrap_or_default();
+    let value = f(&rows.select(&arg));
+    vec![value; arg.len()]
+}
+
+/// Summary rows (min, max, mean and standard deviation) for each column, ignoring NaN values.
+pub(crate) fn summarize(columns: &[Vec<f32>]) -> Vec<SummaryRow> {
+    let columns: Vec<Vec<f32>> = columns.iter().map(|col| without_nan(col)).collect();
+    let stats: [(&str, Reducer); 4] = [
+        ("min", min),
+        ("max", max),
+        ("mean", mean),
+        ("stddev", stddev),
+    ];
+
+    stats
+        .into_iter()
+        .map(|(label, f)| SummaryRow::new(label, columns.iter().map(|col| f(col)).collect()))
+        .collect()
+}
+
+fn without_nan(values: &[f32]) -> Vec<f32> {
+    values.iter().copied().filter(|v| !v.is_nan()).collect()
+}
+
+/// Linearly interpolated quantile (`q` in 0..=1) of the values.
+fn quantile(values: &[f32], q: f32) -> f32 {
+    if values.is_empty() {
+        return f32::NAN;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+
+    let rank = q * (sorted.len() - 1) as f32;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f32)
+}
+
+fn mean(window: &[f32]) -> f32 {
+    window.iter().sum::<f32>() / window.len() as f32
+}
+
+/// Sample standard deviation of the window.
+fn stddev(window: &[f32]) -> f32 {
+    let mean = mean(window);
+    let var = window.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / (window.len() as f32 - 1.0);
+    var.sqrt()
+}
+
+fn max(window: &[f32]) -> f32 {
+    window.iter().copied().reduce(f32::max).unwrap_or(f32::NAN)
+}
+
+fn min(window: &[f32]) -> f32 {
+    window.iter().copied().reduce(f32::min).unwrap_or(f32::NAN)
+}
+
+/// Exponential moving average with smoothing factor `2 / (period + 1)`,
+/// seeded with the simple average of the first `period` values.
+fn ema(arg: Option<Vec<f32>>, period: usize) -> Vec<f32> {
+    let arg = arg.unwrap_or_default();
+    let alpha = 2.0 / (period as f32 + 1.0);
+    let mut result = vec![f32::NAN; arg.len()];
+    if arg.len() < period {
+        return result;
+    }
+
+    let mut prev = arg[..period].iter().sum::<f32>() / period as f32;
+    result[period - 1] = prev;
+    for i in period..arg.len() {
+        prev = alpha * arg[i] + (1.0 - alpha) * prev;
+        result[i] = prev;
+    }
+    result
+}
+
+/// Combines each value with its predecessor. The first row has no predecessor and is NaN.
+fn with_previous(arg: Option<Vec<f32>>, f: fn(f32, f32) -> f32) -> Vec<f32> {
+    let arg = arg.unwrap_or_default();
+    (0..arg.len())
+        .map(|i| match i.checked_sub(1) {
+            Some(prev) => f(arg[i], arg[prev]),
+            None => f32::NAN,
+        })
+        .collect()
+}
+
+/// Shifts values forward by `offset` rows (backward if negative).
+/// Rows shifted in from outside the series are NaN.
+fn shift(arg: Option<Vec<f32>>, offset: isize) -> Vec<f32> {
+    let arg = arg.unwrap_or_default();
+    (0..arg.len() as isize)
+        .map(|i| match usize::try_from(i - offset) {
+            Ok(src) if src < arg.len() => arg[src],
+            _ => f32::NAN,
+        })
+        .collect()
+}
+
+/// Running accumulation starting at row `start`; rows before it are NaN.
+fn cumulative(arg: Option<Vec<f32>>, start: usize, init: f32, f: fn(f32, f32) -> f32) -> Vec<f32> {
+    let mut acc = init;
+    arg.unwrap_or_default()
+        .into_iter()
+        .enumerate()
+        .map(|(i, v)| {
+            if i < start {
+                return f32::NAN;
+            }
+            acc = f(acc, v);
+            acc
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use query_parser::parse_expr;
+
+    fn eval(src: &str, data: Vec<f32>) -> Vec<f32> {
+        eval_with_warmup(src, data, 0)
+    }
+
+    fn eval_with_warmup(src: &str, data: Vec<f32>, warmup: usize) -> Vec<f32> {
+        match parse_expr(src).unwrap() {
+            Expr::Call(func, args) => {
+                let rows = Rows::new(data.len(), warmup);
+                apply_function(func, &args, &rows, |_| Ok(data.clone())).unwrap()
+            }
+            other => panic!("Expected function call, got {other}"),
+        }
+    }
+
+    #[test]
+    fn test_rolling_functions() {
+        let data = vec![1.0, 2.0, 3.0, 4.0, 5.0];
+        let sma = eval("SMA(x.close, 3)", data.clone());
+        assert!(sma[0].is_nan() && sma[1].is_nan());
+        assert_eq!(vec![2.0, 3.0, 4.0], sma[2..]);
+
+        assert_eq!(
+            vec![3.0, 4.0, 5.0],
+            eval("ROLLING_MAX(x.close, 3)", data.clone())[2..]
+        );
+        assert_eq!(
+            vec![1.0, 2.0, 3.0],
+            eval("ROLLING_MIN(x.close, 3)", data.clone())[2..]
+        );
+        assert_eq!(vec![1.0, 1.0, 1.0], eval("STDDEV(x.close, 3)", data)[2..]);
+    }
+
+    #[test]
+    fn test_ema() {
+        let ema = eval("EMA(x.close, 3)", vec![2.0, 4.0, 6.0, 8.0, 10.0]);
+        assert!(ema[1].is_nan());
+        assert_eq!(vec![4.0, 6.0, 8.0], ema[2..]);
+    }
+
+    #[test]
+    fn test_series_functions() {
+        let data = vec![2.0, 4.0, 5.0, 10.0];
+        let diff = eval("DIFF(x.close)", data.clone());
+        assert!(diff[0].is_nan());
+        assert_eq!(vec![2.0, 1.0, 5.0], diff[1..]);
+
+        let pct = eval("PCT_CHANGE(x.close)", data.clone());
+        assert_eq!(vec![1.0, 0.25, 1.0], pct[1..]);
+
+        let lag = eval("LAG(x.close, 2)", data.clone());
+        assert!(lag[0].is_nan() && lag[1].is_nan());
+        assert_eq!(vec![2.0, 4.0], lag[2..]);
+
+        let lead = eval("LEAD(x.close, 1)", data.clone());
+        assert_eq!(vec![4.0, 5.0, 10.0], lead[..3]);
+        assert!(lead[3].is_nan());
+    }
+
+    #[test]
+    fn test_cumulative_functions() {
+        let data = vec![2.0, 4.0, 5.0, 10.0];
+        assert_eq!(
+            vec![2.0, 6.0, 11.0, 21.0],
+            eval("CUMSUM(x.close)", data.clone())
+        );
+
+        let cumprod = eval_with_warmup("CUMPROD(x.close)", data, 2);
+        assert!(cumprod[0].is_nan() && cumprod[1].is_nan());
+        assert_eq!(vec![5.0, 50.0], cumprod[2..]);
+    }
+
+    #[test]
+    fn test_aggregate_functions() {
+        let data = vec![100.0, 4.0, 1.0, f32::NAN, 3.0, 2.0];
+        let eval = |src| eval_with_warmup(src, data.clone(), 1);
+        assert_eq!(vec![2.5; 6], eval("AVG(x.close)"));
+        assert_eq!(vec![10.0; 6], eval("SUM(x.close)"));
+        assert_eq!(vec![4.0; 6], eval("COUNT(x.close)"));
+        assert_eq!(vec![1.0; 6], eval("MIN(x.close)"));
+        assert_eq!(vec![4.0; 6], eval("MAX(x.close)"));
+        assert_eq!(vec![2.5; 6], eval("MEDIAN(x.close)"));
+        assert_eq!(vec![3.25; 6], eval("PERCENTILE(x.close, 75)"));
+    }
+
+    #[test]
+    fn test_summarize() {
+        let summary = summarize(&[vec![1.0, 2.0, 3.0], vec![f32::NAN, 5.0, 5.0]]);
+        let values: Vec<(&str, &[f32])> = summary.iter().map(|r| (r.label(), r.values())).collect();
+        assert_eq!(
+            vec![
+                ("min", &[1.0, 5.0][..]),
+                ("max", &[3.0, 5.0][..]),
+                ("mean", &[2.0, 5.0][..]),
+                ("stddev", &[1.0, 0.0][..]),
+            ],
+            values
+        );
+    }
+}
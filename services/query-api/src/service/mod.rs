@@ -1,3 +1,5 @@
+mod functions;
 mod query_service;
+mod resample;
 
 pub use query_service::QueryService;
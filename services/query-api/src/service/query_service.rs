@@ -1,9 +1,17 @@
-use std::sync::Arc;
+use std::{cmp::Ordering, sync::Arc};
 
+use chrono::Utc;
 use futures::future::try_join_all;
-use query_parser::{Expr, Query};
+use query_parser::{
+    Expr, MetricCatalog, Query, SortKey, SortOrder, Universes, ValidationIssue, is_true,
+    validate_at,
+};
 use tokio::task;
 
+use super::{
+    functions::{Rows, apply_function, summarize},
+    resample::{align, resample},
+};
 use crate::{
     domain::{SymbolData, Table},
     error::AppError,
@@ -14,47 +22,180 @@ use crate::{
 #[derive(Clone)]
 pub struct QueryService {
     metrics_repo: Arc<dyn MetricsRepository>,
+    catalog: Arc<MetricCatalog>,
+    universes: Arc<Universes>,
 }
 
 impl QueryService {
-    pub fn new(metrics_repo: Arc<dyn MetricsRepository>) -> Self {
-        Self { metrics_repo }
+    pub fn new(
+        metrics_repo: Arc<dyn MetricsRepository>,
+        catalog: MetricCatalog,
+        universes: Universes,
+    ) -> Self {
+        Self {
+            metrics_repo,
+            catalog: Arc::new(catalog),
+            universes: Arc::new(universes),
+        }
     }
 
-    pub async fn run_query(&self, query: &Query) -> Result<Table, AppError> {
-        let plan = QueryPlan::from(query);
+    /// The metrics queries may refer to.
+    pub fn catalog(&self) -> &MetricCatalog {
+        &self.catalog
+    }
+
+    /// The universes queries may refer to, to be expanded before the query runs.
+    pub fn universes(&self) -> &Universes {
+        &self.universes
+    }
+
+    /// Expands the universes and symbol sets of, validates and runs the query. Returns the table
+    /// with the warnings of the query; a query with validation errors is not run.
+    /// A relative range is resolved once, when the query starts.
+    pub async fn run_query(
+        &self,
+        query: &Query,
+    ) -> Result<(Table, Vec<ValidationIssue>), AppError> {
+        let query = self
+            .universes
+            .expand(query)
+            .map_err(AppError::ValidationError)?;
+        let now = Utc::now();
+        let issues = validate_at(&query, &self.catalog, now);
+        if issues.iter().any(ValidationIssue::is_error) {
+            return Err(AppError::ValidationError(issues));
+        }
+        let plan = QueryPlan::for_query(&query, &self.catalog, now);
         let data = self.metrics_repo.get_metrics_for_query_plan(&plan).await?;
-        self.compute_table(query, data).await
+        let data = match plan.resample() {
+            Some(_) => resample(data, &plan, &self.catalog),
+            None => data,
+        };
+        let data = align(data, &plan);
+        let table = self.compute_table(&query, data, &plan).await?;
+        Ok((table, issues))
     }
 
-    async fn compute_table(&self, query: &Query, data: SymbolData) -> Result<Table, AppError> {
-        let data = Arc::new(data);
+    /// Computes the output table. The data has a value for each row of the `plan` (see
+    /// `align`), including the warm-up rows (history needed by window functions), which
+    /// are dropped after evaluation; each row returned has the time it starts at.
+    /// Rows not meeting the WHERE condition are removed (and left out of aggregates),
+    /// then the rest are sorted and limited; the summary describes the rows returned.
+    /// If every column is an aggregate, the table has a single row.
+    async fn compute_table(
+        &self,
+        query: &Query,
+        data: SymbolData,
+        plan: &QueryPlan,
+    ) -> Result<Table, AppError> {
+        let (rows_count, warmup) = (plan.rows_count(), plan.warmup());
+        let mut exprs: Vec<Expr> = query.expressions().cloned().collect();
+        let aggregate_only = exprs.iter().all(Expr::is_aggregate);
 
-        let mut columns: Vec<Vec<f32>> = vec![self.timestamps_column(&query)];
+        // index of the sort column in a row; an expression not in the output
+        // is computed as an extra column, removed after sorting
+        let columns_count = exprs.len();
+        let sort_column = match query.order_by().map(|order_by| order_by.key()) {
+            Some(SortKey::Alias(alias)) => {
+                let pos = query
+                    .columns()
+                    .iter()
+                    .position(|c| c.alias() == Some(alias.as_str()))
+                    .ok_or_else(|| AppError::DataError(format!("Unknown column: {alias}")))?;
+                Some(pos + 1)
+            }
+            Some(SortKey::Expr(expr)) => match exprs.iter().position(|e| e == expr) {
+                Some(pos) => Some(pos + 1),
+                None => {
+                    exprs.push(expr.clone());
+                    Some(exprs.len())
+                }
+            },
+            None => None,
+        };
+
+        // the condition is evaluated first, so aggregates reduce the rows meeting it only
+        let rows = Rows::new(rows_count.saturating_add(warmup), warmup);
+        let (rows, mask) = match query.filter() {
+            Some(filter) => {
+                let condition = create_column(filter, &data, &rows)?;
+                let selected: Vec<bool> = condition.iter().copied().map(is_true).collect();
+                let mask = fit_rows(condition, warmup, rows_count)
+                    .into_iter()
+                    .map(is_true)
+                    .collect::<Vec<bool>>();
+                (rows.filtered(&selected), Some(mask))
+            }
+            None => (rows, None),
+        };
+
+        // the first column is the index of the row, to find its time once sorted
+        let mut columns: Vec<Vec<f32>> = vec![(0..rows_count).map(|i| i as f32).collect()];
         columns.extend(
-            self.compute_all_columns(query.expressions(), data, query.rows_count())
-                .await?,
+            self.compute_all_columns(&exprs, Arc::new(data), Arc::new(rows))
+                .await?
+                .into_iter()
+                .map(|col| fit_rows(col, warmup, rows_count)),
         );
+        match (aggregate_only, mask) {
+            (true, _) => columns.iter_mut().for_each(|col| col.truncate(1)),
+            (false, Some(mask)) => filter_rows(&mut columns, &mask),
+            (false, None) => {}
+        }
+
+        let mut rows = self.transpose(columns);
 
-        let rows = self.transpose(columns);
+        if let (Some(order_by), Some(sort_column)) = (query.order_by(), sort_column) {
+            sort_rows(&mut rows, sort_column, order_by.order());
+        }
+        let row_times = plan.row_times();
+        let (times, rows): (Vec<_>, Vec<_>) = rows
+            .into_iter()
+            .skip(query.offset())
+            .take(query.limit().unwrap_or(usize::MAX))
+            .map(|row| {
+                (
+                    row_times[warmup + row[0] as usize],
+                    row[1..=columns_count].to_vec(),
+                )
+            })
+            .unzip();
+
+        let summary = match query.summary() {
+            true => {
+                let columns: Vec<Vec<f32>> = (0..columns_count)
+                    .map(|i| rows.iter().map(|row: &Vec<f32>| row[i]).collect())
+                    .collect();
+                summarize(&columns)
+            }
+            false => Vec::new(),
+        };
 
-        let mut headers = vec!["time step".to_string()];
-        headers.extend(query.expressions().iter().map(|expr| expr.to_string()));
+        let mut headers = vec!["time".to_string()];
+        headers.extend(query.columns().iter().map(|column| column.name()));
 
-        Ok(Table::new(headers, rows))
+        let boolean_columns = query
+            .expressions()
+            .map(Expr::is_boolean)
+            .collect::<Vec<bool>>();
+
+        Ok(Table::new(headers, times, rows)
+            .with_boolean_columns(boolean_columns)
+            .with_summary(summary))
     }
 
     async fn compute_all_columns(
         &self,
         exprs: &[Expr],
         symbol: Arc<SymbolData>,
-        size: usize,
+        rows: Arc<Rows>,
     ) -> Result<Vec<Vec<f32>>, AppError> {
         let exprs = exprs.to_vec();
 
         let tasks = exprs.into_iter().map(|expr| {
             let data = Arc::clone(&symbol);
-            task::spawn_blocking(move || create_column(&expr, &data, size))
+            let rows = Arc::clone(&rows);
+            task::spawn_blocking(move || create_column(&expr, &data, &rows))
         });
 
         let results = try_join_all(tasks)
@@ -81,34 +222,326 @@ impl QueryService {
 
         rows
     }
-
-    /// Produces vector of timestamps (or - precisely - time steps)
-    fn timestamps_column(&self, query: &Query) -> Vec<f32> {
-        std::iter::successors(Some(0f32), move |prev| {
-            Some(prev + (query.step().value() as f32))
-        })
-        .take(query.rows_count())
-        .collect()
-    }
 }
 
-fn create_column(expr: &Expr, data: &SymbolData, size: usize) -> Result<Vec<f32>, AppError> {
+/// Evaluates an expression into a column of a value per row.
+fn create_column(expr: &Expr, data: &SymbolData, rows: &Rows) -> Result<Vec<f32>, AppError> {
+    let size = rows.size();
     let col = match expr {
-        Expr::Value(val) => std::iter::repeat(val.clone() as f32)
-            .take(size)
-            .collect::<Vec<_>>(),
+        Expr::Value(val) => std::iter::repeat_n(*val as f32, size).collect::<Vec<_>>(),
+
+        Expr::Data(sm) => data
+            .get(sm.qualified_symbol().as_ref())
+            .and_then(|metrics| metrics.get(sm.metric()))
+            .ok_or_else(|| AppError::DataError(format!("no data for {sm}")))?
+            .clone(),
+
+        Expr::Call(func, args) => {
+            apply_function(*func, args, rows, |arg| create_column(arg, data, rows))?
+        }
+
+        Expr::Neg(expr) => create_column(expr, data, rows)?
+            .into_iter()
+            .map(|v| -v)
+            .collect::<Vec<f32>>(),
 
-        Expr::Data(sm) => data[sm.symbol()][&sm.metric()].clone(),
+        Expr::Not(expr) => create_column(expr, data, rows)?
+            .into_iter()
+            .map(|v| match v.is_nan() {
+                true => f32::NAN,
+                false => {
+                    if v == 0.0 {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                }
+            })
+            .collect::<Vec<f32>>(),
+
+        Expr::Case(branches, default) => {
+            // each row takes the value of the first branch whose condition holds
+            let mut col = match default {
+                Some(default) => create_column(default, data, rows)?,
+                None => vec![f32::NAN; size],
+            };
+            let mut decided = vec![false; size];
+            for (cond, value) in branches {
+                let cond = create_column(cond, data, rows)?;
+                let value = create_column(value, data, rows)?;
+                for i in 0..size {
+                    if !decided[i] && is_true(cond[i]) {
+                        col[i] = value[i];
+                        decided[i] = true;
+                    }
+                }
+            }
+            col
+        }
 
         Expr::Binary(left, op, right) => {
-            let left = create_column(left, data, size)?;
-            let right = create_column(right, data, size)?;
+            let left = create_column(left, data, rows)?;
+            let right = create_column(right, data, rows)?;
             let opfn = op.opfn();
             left.iter()
                 .zip(right)
-                .map(|(a, b)| opfn(a.clone(), b.clone()))
+                .map(|(a, b)| opfn(*a, b))
                 .collect::<Vec<f32>>()
         }
     };
     Ok(col)
 }
+
+/// Sorts rows by the value in the given column; `NaN` values go last in either order.
+/// The sort is stable, so rows with equal values stay in chronological order.
+fn sort_rows(rows: &mut [Vec<f32>], column: usize, order: SortOrder) {
+    rows.sort_by(|a, b| {
+        let (a, b) = (a[column], b[column]);
+        match (a.is_nan(), b.is_nan(), order) {
+            (true, true, _) => Ordering::Equal,
+            (true, false, _) => Ordering::Greater,
+            (false, true, _) => Ordering::Less,
+            (false, false, SortOrder::Asc) => a.total_cmp(&b),
+            (false, false, SortOrder::Desc) => b.total_cmp(&a),
+        }
+    });
+}
+
+/// Drops the warm-up rows of a column, then cuts or pads (with `NaN`) it to `rows_count`
+/// values, so all columns line up with the rows of the table.
+fn fit_rows(mut col: Vec<f32>, warmup: usize, rows_count: usize) -> Vec<f32> {
+    col.drain(..warmup.min(col.len()));
+    col.resize(rows_count, f32::NAN);
+    col
+}
+
+/// Keeps only the rows (values of each column) for which the mask is true.
+fn filter_rows(columns: &mut [Vec<f32>], mask: &[bool]) {
+    for col in columns.iter_mut() {
+        let mut keep = mask.iter();
+        col.retain(|_| keep.next().copied().unwrap_or(false));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        domain::MetricData,
+        shared::{DateRange, TargetMetrics},
+    };
+    use query_parser::{Metric, parse_condition, parse_expr, parse_query};
+    use std::{collections::HashMap, time::Duration};
+
+    /// Repository returning the same data for every query.
+    struct FixedMetrics(MetricData);
+
+    #[async_trait::async_trait]
+    impl MetricsRepository for FixedMetrics {
+        async fn get_metrics_for_symbol(
+            &self,
+            _metrics: &TargetMetrics,
+            _date_range: &DateRange,
+            _step: Duration,
+        ) -> Result<MetricData, AppError> {
+            Ok(self.0.clone())
+        }
+
+        async fn get_metrics_for_query_plan(
+            &self,
+            plan: &QueryPlan,
+        ) -> Result<SymbolData, AppError> {
+            let data = plan
+                .targets()
+                .map(|t| (t.qualified_symbol(), self.0.clone()));
+            Ok(data.collect())
+        }
+    }
+
+    /// Hourly bars of close values from 2025-06-02 00:00 on, but for the skipped hours.
+    fn hourly_close(values: &[f32], skipped: &[usize]) -> MetricData {
+        let from: chrono::DateTime<Utc> = "2025-06-02T00:00:00Z".parse().unwrap();
+        let hours = (0..values.len() + skipped.len()).filter(|hour| !skipped.contains(hour));
+        MetricData::new(
+            hours
+                .map(|hour| from + chrono::TimeDelta::hours(hour as i64))
+                .collect(),
+            HashMap::from([(Metric::CLOSE, values.to_vec())]),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_aggregates_with_filter() {
+        let data = hourly_close(&[1.0, 2.0, 3.0, 4.0], &[]);
+        let service = QueryService::new(
+            Arc::new(FixedMetrics(data)),
+            MetricCatalog::well_known().clone(),
+            Universes::new(),
+        );
+        let rows = async |src: &str| {
+            let query = parse_query(&format!(
+                "{src} FROM '2025-06-02T00:00:00Z' TO '2025-06-02T04:00:00Z' STEP 1 hour \
+                 WHERE AAPL.close > 2"
+            ))
+            .unwrap();
+            let (table, _) = service.run_query(&query).await.unwrap();
+            table.rows().cloned().collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec![vec![3.5, 2.0]],
+            rows("GET AVG(AAPL.close), COUNT(AAPL.close)").await
+        );
+        assert_eq!(
+            vec![vec![3.0, -0.5], vec![4.0, 0.5]],
+            rows("GET AAPL.close, AAPL.close - AVG(AAPL.close)").await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rows_of_missing_and_extra_bars() {
+        // the 01:00 bar is missing, the 03:00 bar is past the range
+        let data = hourly_close(&[1.0, 3.0, 4.0], &[1]);
+        let service = QueryService::new(
+            Arc::new(FixedMetrics(data)),
+            MetricCatalog::well_known().clone(),
+            Universes::new(),
+        );
+        let query = parse_query(
+            "GET AAPL.close, AAPL.close > 2 FROM '2025-06-02T00:00:00Z' TO '2025-06-02T03:00:00Z' \
+             STEP 1 hour ORDER BY AAPL.close DESC SUMMARY",
+        )
+        .unwrap();
+        let (table, _) = service.run_query(&query).await.unwrap();
+
+        let times: Vec<String> = table
+            .times()
+            .map(|time| time.format("%H:%M").to_string())
+            .collect();
+        assert_eq!(vec!["02:00", "00:00", "01:00"], times);
+        let rows: Vec<Vec<f32>> = table.rows().cloned().collect();
+        assert_eq!(vec![vec![3.0, 1.0], vec![1.0, 0.0]], rows[..2]);
+        assert!(rows[2][0].is_nan());
+        let mean = table.summary().find(|row| row.label() == "mean").unwrap();
+        assert_eq!(2.0, mean.values()[0]);
+        assert_eq!(
+            vec!["time", "AAPL.close", "AAPL.close > 2"],
+            table.headers().collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_query_expands_universes() {
+        let data = hourly_close(&[1.0, 2.0], &[]);
+        let service = QueryService::new(
+            Arc::new(FixedMetrics(data)),
+            MetricCatalog::well_known().clone(),
+            Universes::new().with("pair", &["AAPL", "MSFT"]),
+        );
+        let query = |columns: &str| {
+            parse_query(&format!(
+                "GET {columns} FROM '2025-06-02T00:00:00Z' TO '2025-06-02T02:00:00Z' STEP 1 hour"
+            ))
+            .unwrap()
+        };
+
+        let (table, _) = service.run_query(&query("@pair.close")).await.unwrap();
+        assert_eq!(
+            vec!["time", "close_AAPL", "close_MSFT"],
+            table.headers().collect::<Vec<_>>()
+        );
+
+        let result = service.run_query(&query("@missing.close")).await;
+        assert!(matches!(result, Err(AppError::ValidationError(_))));
+    }
+
+    #[test]
+    fn test_filter_rows() {
+        let data: SymbolData = HashMap::from([(
+            "AAPL".to_string(),
+            MetricData::new(
+                Vec::new(),
+                HashMap::from([
+                    (Metric::OPEN, vec![1.0, 5.0, 3.0, 4.0]),
+                    (Metric::CLOSE, vec![2.0, 4.0, 3.5, f32::NAN]),
+                    (Metric::VOLUME, vec![3000.0, 3000.0, 1000.0, 3000.0]),
+                ]),
+            ),
+        )]);
+        let cond =
+            parse_condition(r"AAPL.volume > 2000 AND AAPL.close > AAPL.open OR AAPL.open = 3")
+                .unwrap();
+        let mask: Vec<bool> = create_column(&cond, &data, &Rows::new(4, 0))
+            .unwrap()
+            .into_iter()
+            .map(is_true)
+            .collect();
+        assert_eq!(vec![true, false, true, false], mask);
+
+        let mut columns = vec![vec![0.0, 1.0, 2.0, 3.0], vec![10.0, 11.0, 12.0, 13.0]];
+        filter_rows(&mut columns, &mask);
+        assert_eq!(vec![vec![0.0, 2.0], vec![10.0, 12.0]], columns);
+    }
+
+    #[test]
+    fn test_conditional_column() {
+        let data: SymbolData = HashMap::from([(
+            "AAPL".to_string(),
+            MetricData::new(
+                Vec::new(),
+                HashMap::from([(Metric::CLOSE, vec![1.0, 5.0, 12.0, f32::NAN])]),
+            ),
+        )]);
+        let expr = parse_expr(
+            r"CASE WHEN AAPL.close > 10 THEN 2 WHEN AAPL.close > 2 THEN 1 ELSE NOT AAPL.close END",
+        )
+        .unwrap();
+        let col = create_column(&expr, &data, &Rows::new(4, 0)).unwrap();
+        assert_eq!(vec![0.0, 1.0, 2.0], col[..3]);
+        // NOT of NaN is NaN rather than true
+        assert!(col[3].is_nan());
+
+        let expr = parse_expr(r"IF(AAPL.close > 2 AND AAPL.close < 10, AAPL.close, 0)").unwrap();
+        assert_eq!(
+            vec![0.0, 5.0, 0.0, 0.0],
+            create_column(&expr, &data, &Rows::new(4, 0)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_missing_data() {
+        let data: SymbolData = HashMap::from([(
+            "AAPL".to_string(),
+            MetricData::new(Vec::new(), HashMap::from([(Metric::CLOSE, vec![1.0])])),
+        )]);
+        let column = |src: &str| create_column(&parse_expr(src).unwrap(), &data, &Rows::new(1, 0));
+
+        assert!(column("AAPL.close").is_ok());
+        for (src, message) in [
+            ("MSFT.close", "no data for MSFT.close"),
+            ("AAPL.vwap + 1", "no data for AAPL.vwap"),
+        ] {
+            match column(src) {
+                Err(AppError::DataError(err)) => assert_eq!(message, err),
+                other => panic!("expected a data error for {src}, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_sort_rows() {
+        let mut rows = vec![
+            vec![0.0, 2.0],
+            vec![1.0, f32::NAN],
+            vec![2.0, 5.0],
+            vec![3.0, 2.0],
+        ];
+        sort_rows(&mut rows, 1, SortOrder::Desc);
+        let times: Vec<f32> = rows.iter().map(|row| row[0]).collect();
+        assert_eq!(vec![2.0, 0.0, 3.0, 1.0], times);
+
+        sort_rows(&mut rows, 1, SortOrder::Asc);
+        let times: Vec<f32> = rows.iter().map(|row| row[0]).collect();
+        assert_eq!(vec![0.0, 3.0, 2.0, 1.0], times);
+    }
+}
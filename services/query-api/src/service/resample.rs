@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use query_parser::{Metric, MetricCatalog, Rollup};
+
+use crate::{
+    domain::{MetricData, SymbolData},
+    shared::QueryPlan,
+};
+
+/// Merges the fetched bars into buckets of the query step, combining each metric
+/// according to its rollup in the `catalog` (i.e. open is the first value, volume
+/// is the sum). Buckets are aligned on the start of the planned range, and each bar
+/// goes to the bucket its timestamp falls in, so missing bars don't shift later buckets.
+/// A bucket without bars has no value (no volume for sums).
+pub(crate) fn resample(data: SymbolData, plan: &QueryPlan, catalog: &MetricCatalog) -> SymbolData {
+    let factor = plan.resample().unwrap_or(1);
+    if factor <= 1 {
+        return data;
+    }
+    let range = plan.range();
+    let from: DateTime<Utc> = range.from().into();
+    let to: DateTime<Utc> = range.to().into();
+    let bucket = TimeDelta::from_std(plan.step())
+        .ok()
+        .and_then(|bar| bar.checked_mul(factor.try_into().ok()?))
+        .unwrap_or(TimeDelta::MAX);
+    let buckets = Buckets { from, to, bucket };
+
+    data.into_iter()
+        .map(|(symbol, metrics)| (symbol, resample_metrics(&metrics, &buckets, catalog)))
+        .collect()
+}
+
+/// Places the bars of each symbol on the rows of the plan (warm-up rows included) by
+/// their start time, so every series has one value per row, however many bars were
+/// fetched. A row without a bar has no value; bars outside of the rows, i.e. of the
+/// partial step at the end of a range which is not a multiple of the step, are left out.
+pub(crate) fn align(data: SymbolData, plan: &QueryPlan) -> SymbolData {
+    let times = plan.row_times();
+    let (Some(from), Some(last)) = (times.first(), times.last()) else {
+        return data;
+    };
+    let bucket = TimeDelta::from_std(plan.row_step()).unwrap_or(TimeDelta::MAX);
+    let to = last
+        .checked_add_signed(bucket)
+        .unwrap_or(DateTime::<Utc>::MAX_UTC);
+    let buckets = Buckets {
+        from: *from,
+        to,
+        bucket,
+    };
+
+    data.into_iter()
+        .map(|(symbol, metrics)| {
+            let indices: Vec<Option<usize>> = metrics
+                .timestamps()
+                .iter()
+                .map(|time| buckets.index(time))
+                .collect();
+            let values = metrics
+                .iter()
+                .map(|(metric, values)| {
+                    let grouped = buckets.group(values, &indices);
+                    let aligned = grouped.iter().map(|b| rollup_bucket(Rollup::Last, b));
+                    (metric.clone(), aligned.collect())
+                })
+                .collect();
+            (symbol, MetricData::new(times.clone(), values))
+        })
+        .collect()
+}
+
+/// Buckets of `bucket` length from `from` up to `to` (the last one may end later).
+struct Buckets {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    bucket: TimeDelta,
+}
+
+impl Buckets {
+    fn count(&self) -> usize {
+        let range = (self.to - self.from).num_seconds().max(0) as u64;
+        let bucket = self.bucket.num_seconds().max(1) as u64;
+        range.div_ceil(bucket) as usize
+    }
+
+    /// Start time of each bucket.
+    fn timestamps(&self) -> Vec<DateTime<Utc>> {
+        (0..self.count())
+            .map(|i| self.from + self.bucket * i as i32)
+            .collect()
+    }
+
+    /// Index of the bucket a bar starting at `time` falls in, if any.
+    fn index(&self, time: &DateTime<Utc>) -> Option<usize> {
+        if *time < self.from || *time >= self.to {
+            return None;
+        }
+        let offset = (*time - self.from).num_seconds();
+        Some((offset / self.bucket.num_seconds().max(1)) as usize)
+    }
+
+    /// Groups values by the bucket of their bar.
+    fn group(&self, values: &[f32], indices: &[Option<usize>]) -> Vec<Vec<f32>> {
+        let mut buckets = vec![Vec::new(); self.count()];
+        for (value, index) in values.iter().zip(indices) {
+            if let Some(index) = index {
+                buckets[*index].push(*value);
+            }
+        }
+        buckets
+    }
+}
+
+fn resample_metrics(
+    metrics: &MetricData,
+    buckets: &Buckets,
+    catalog: &MetricCatalog,
+) -> MetricData {
+    let indices: Vec<Option<usize>> = metrics
+        .timestamps()
+        .iter()
+        .map(|time| buckets.index(time))
+        .collect();
+    let volume = metrics
+        .get(&Metric::VOLUME)
+        .map(|volume| buckets.group(volume, &indices));
+    let values = metrics
+        .iter()
+        .map(|(metric, values)| {
+            // metrics are validated against the catalog, the fallback is for safety only
+            let rollup = catalog
+                .get(metric)
+                .map_or(Rollup::Last, |info| info.rollup());
+            let grouped = buckets.group(values, &indices);
+            let resampled = match (rollup, &volume) {
+                (Rollup::VolumeWeighted, Some(volume)) => grouped
+                    .iter()
+                    .zip(volume)
+                    .map(|(values, weights)| weighted_mean(values, weights))
+                    .collect(),
+                (rollup, _) => grouped.iter().map(|b| rollup_bucket(rollup, b)).collect(),
+            };
+            (metric.clone(), resampled)
+        })
+        .collect::<HashMap<_, _>>();
+    MetricData::new(buckets.timestamps(), values)
+}
+
+fn rollup_bucket(rollup: Rollup, bucket: &[f32]) -> f32 {
+    match rollup {
+        Rollup::First => bucket.first().copied().unwrap_or(f32::NAN),
+        Rollup::Last => bucket.last().copied().unwrap_or(f32::NAN),
+        Rollup::Max => bucket.iter().copied().fold(f32::NAN, f32::max),
+        Rollup::Min => bucket.iter().copied().fold(f32::NAN, f32::min),
+        Rollup::Sum => bucket.iter().sum(),
+        Rollup::VolumeWeighted => bucket.iter().sum::<f32>() / bucket.len() as f32,
+    }
+}
+
+/// Mean of the values weighted by volume; plain mean if there was no volume in the bucket.
+fn weighted_mean(values: &[f32], weights: &[f32]) -> f32 {
+    let total: f32 = weights.iter().sum();
+    if total == 0.0 {
+        return rollup_bucket(Rollup::VolumeWeighted, values);
+    }
+    values.iter().zip(weights).map(|(v, w)| v * w).sum::<f32>() / total
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::shared::DateRange;
+    use std::time::Duration;
+
+    #[test]
+    fn test_resample_rollups() {
+        let from: DateTime<Utc> = "2025-06-02T09:00:00Z".parse().unwrap();
+        let to: DateTime<Utc> = "2025-06-02T12:00:00Z".parse().unwrap();
+        // hourly buckets of 30 minute bars; the 10:30 bar is missing
+        let timestamps = ["09:00", "09:30", "10:00", "11:00", "11:30"]
+            .iter()
+            .map(|time| format!("2025-06-02T{time}:00Z").parse().unwrap())
+            .collect();
+        let metrics = MetricData::new(
+            timestamps,
+            HashMap::from([
+                (Metric::OPEN, vec![1.0, 2.0, 3.0, 4.0, 5.0]),
+                (Metric::CLOSE, vec![1.0, 2.0, 3.0, 4.0, 5.0]),
+                (Metric::MAX, vec![3.0, 7.0, 5.0, 2.0, 6.0]),
+                (Metric::MIN, vec![3.0, 1.0, 5.0, 2.0, 6.0]),
+                (Metric::VOLUME, vec![10.0, 30.0, 0.0, 0.0, 5.0]),
+                (Metric::AVG, vec![2.0, 4.0, 1.0, 3.0, 8.0]),
+            ]),
+        );
+        let range = DateRange::new(from.into(), to.into());
+        let plan = QueryPlan::new(Vec::new(), range, Duration::from_secs(1800), 0, 3)
+            .with_resample(Some(2));
+        let data = resample(
+            HashMap::from([("AAPL".to_string(), metrics)]),
+            &plan,
+            MetricCatalog::well_known(),
+        );
+        let data = &data["AAPL"];
+
+        let hours: Vec<String> = data
+            .timestamps()
+            .iter()
+            .map(|time| time.format("%H:%M").to_string())
+            .collect();
+        assert_eq!(vec!["09:00", "10:00", "11:00"], hours);
+        assert_eq!(vec![1.0, 3.0, 4.0], data[&Metric::OPEN]);
+        assert_eq!(vec![2.0, 3.0, 5.0], data[&Metric::CLOSE]);
+        assert_eq!(vec![7.0, 5.0, 6.0], data[&Metric::MAX]);
+        assert_eq!(vec![1.0, 5.0, 2.0], data[&Metric::MIN]);
+        assert_eq!(vec![40.0, 0.0, 5.0], data[&Metric::VOLUME]);
+        assert_eq!(vec![3.5, 1.0, 8.0], data[&Metric::AVG]);
+    }
+
+    #[test]
+    fn test_align_on_rows() {
+        let from: DateTime<Utc> = "2025-06-02T09:00:00Z".parse().unwrap();
+        let to: DateTime<Utc> = "2025-06-02T12:30:00Z".parse().unwrap();
+        // the 10:00 bar is missing, the 12:00 bar starts the partial step
+        let timestamps = ["09:00", "11:00", "12:00"]
+            .iter()
+            .map(|time| format!("2025-06-02T{time}:00Z").parse().unwrap())
+            .collect();
+        let metrics = MetricData::new(
+            timestamps,
+            HashMap::from([(Metric::CLOSE, vec![1.0, 3.0, 4.0])]),
+        );
+        let range = DateRange::new(from.into(), to.into());
+        let plan = QueryPlan::new(Vec::new(), range, Duration::from_secs(3600), 1, 2);
+        let data = align(HashMap::from([("AAPL".to_string(), metrics)]), &plan);
+        let data = &data["AAPL"];
+
+        assert_eq!(plan.row_times(), data.timestamps());
+        let close = &data[&Metric::CLOSE];
+        assert_eq!(3, close.len());
+        assert_eq!((1.0, 3.0), (close[0], close[2]));
+        assert!(close[1].is_nan());
+    }
+}
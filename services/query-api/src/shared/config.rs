@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+
+use query_parser::{Metric, MetricInfo, Universes};
 use serde::Deserialize;
 
 pub const MAX_HEADER_WIDTH: usize = 10;
@@ -6,4 +9,10 @@ pub const MAX_HEADER_WIDTH: usize = 10;
 pub struct Config {
     pub query_server: String,
     pub graphql_server: String,
+    /// Metrics besides the well-known ones, by name.
+    #[serde(default)]
+    pub metrics: HashMap<Metric, MetricInfo>,
+    /// Named lists of symbols queries refer to as `@name`.
+    #[serde(default)]
+    pub universes: Universes,
 }
@@ -1,6 +1,9 @@
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug)]
+use chrono::{DateTime, Utc};
+use query_parser::TimeRange;
+
+#[derive(Debug, Clone, Copy)]
 pub struct DateRange {
     from: SystemTime,
     to: SystemTime
@@ -12,6 +15,13 @@ impl DateRange {
         Self { from, to }
     }
 
+    /// Anchors the query time range: relative ranges are resolved against `now`
+    /// (with calendar-aware months), absolute ranges are used as they are.
+    pub fn resolve(range: &TimeRange, now: DateTime<Utc>) -> Self {
+        let (from, to) = range.bounds(now);
+        DateRange::new(from.into(), to.into())
+    }
+
     pub fn from_now(delta: Duration) -> Self {
         let to = SystemTime::now();
         let from = to.checked_sub(delta).unwrap();
@@ -25,4 +35,18 @@ impl DateRange {
     pub fn to(&self) -> SystemTime {
         self.to
     }
+
+    /// Number of whole steps in the range, at least 1 (see `validate` for a step
+    /// longer than the range).
+    pub fn steps(&self, step: Duration) -> usize {
+        let length = self.to.duration_since(self.from).unwrap_or_default();
+        let steps = length.as_secs().checked_div(step.as_secs()).unwrap_or(0);
+        steps.max(1) as usize
+    }
+
+    /// Returns the range with its start moved `delta` earlier, at most to the epoch.
+    pub fn extended_back(&self, delta: Duration) -> Self {
+        let from = self.from.checked_sub(delta).unwrap_or(UNIX_EPOCH).max(UNIX_EPOCH);
+        Self { from, to: self.to }
+    }
 }
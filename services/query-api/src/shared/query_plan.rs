@@ -1,58 +1,134 @@
 use std::{collections::HashMap, time::Duration};
 
-use query_parser::{Expr, Query, SymbolMetric};
+use chrono::{DateTime, TimeDelta, Utc};
+use query_parser::{Expr, Metric, MetricCatalog, OrderBy, Query, Rollup, SymbolMetric};
 
-use super::TargetMetrics;
+use super::{DateRange, TargetMetrics};
 
 #[derive(Debug)]
 pub struct QueryPlan {
     targets: Vec<TargetMetrics>,
-    range: Duration,
+    range: DateRange,
     step: Duration,
+    warmup: usize,
+    rows_count: usize,
+    resample: Option<usize>,
 }
 
 impl QueryPlan {
-    pub fn new(targets: Vec<TargetMetrics>, range: Duration, step: Duration) -> Self {
+    pub fn new(
+        targets: Vec<TargetMetrics>,
+        range: DateRange,
+        step: Duration,
+        warmup: usize,
+        rows_count: usize,
+    ) -> Self {
         Self {
             targets,
             range,
             step,
+            warmup,
+            rows_count,
+            resample: None,
         }
     }
 
+    /// Sets the number of fetched bars merged into a single query step.
+    pub fn with_resample(mut self, resample: Option<usize>) -> Self {
+        self.resample = resample;
+        self
+    }
+
     pub fn targets(&self) -> impl Iterator<Item = &TargetMetrics> {
         self.targets.iter()
     }
 
-    pub fn range(&self) -> Duration {
+    pub fn range(&self) -> DateRange {
         self.range
     }
 
     pub fn step(&self) -> Duration {
         self.step
     }
-}
 
-impl From<&Query> for QueryPlan {
-    fn from(query: &Query) -> Self {
+    /// Number of extra steps fetched before the query range (history for window functions).
+    pub fn warmup(&self) -> usize {
+        self.warmup
+    }
+
+    /// Number of steps of the query range, i.e. rows of the result before filtering.
+    pub fn rows_count(&self) -> usize {
+        self.rows_count
+    }
+
+    /// Number of fetched bars merged into a single query step, if the data is resampled.
+    pub fn resample(&self) -> Option<usize> {
+        self.resample
+    }
+
+    /// Length of a row, the step of the query (fetched bars are shorter if resampled).
+    pub fn row_step(&self) -> Duration {
+        let factor = self.resample.unwrap_or(1).try_into().unwrap_or(u32::MAX);
+        self.step.saturating_mul(factor)
+    }
+
+    /// Start time of each row, warm-up rows first: the planned range cut into steps
+    /// of the query.
+    pub fn row_times(&self) -> Vec<DateTime<Utc>> {
+        let from: DateTime<Utc> = self.range.from().into();
+        let step = TimeDelta::from_std(self.row_step()).unwrap_or(TimeDelta::MAX);
+        (0..self.warmup.saturating_add(self.rows_count))
+            .map(|i| {
+                step.checked_mul(i.try_into().unwrap_or(i32::MAX))
+                    .and_then(|offset| from.checked_add_signed(offset))
+                    .unwrap_or(DateTime::<Utc>::MAX_UTC)
+            })
+            .collect()
+    }
+
+    /// Plans fetching the data of a query: the metrics of each symbol, over the query
+    /// range extended back by the warmup. The `catalog` tells how metrics are resampled.
+    /// A relative range is resolved against `now`, once for the whole plan.
+    pub fn for_query(query: &Query, catalog: &MetricCatalog, now: DateTime<Utc>) -> Self {
         let mut targets: HashMap<String, TargetMetrics> = HashMap::with_capacity(5);
 
+        // expressions of the WHERE and ORDER BY clauses need data too, even if not in the output
+        let order_by = query.order_by().and_then(OrderBy::expr);
+        let exprs: Vec<&Expr> = query
+            .expressions()
+            .chain(query.filter())
+            .chain(order_by)
+            .collect();
+
         let mut symbols: Vec<&SymbolMetric> = Vec::new();
-        for expr in query.expressions() {
-            collect_symbols(&expr, &mut symbols);
+        for expr in exprs.iter() {
+            collect_symbols(expr, &mut symbols);
         }
 
         for sm in symbols {
+            // the same ticker on different exchanges is a different target
             let target = targets
-                .entry(sm.symbol().to_string())
-                .or_insert_with(|| TargetMetrics::new(sm.symbol()));
-            target.add_metric(sm.metric());
+                .entry(sm.qualified_symbol().into_owned())
+                .or_insert_with(|| TargetMetrics::new(sm.symbol()).with_exchange(sm.exchange()));
+            target.add_metric(sm.metric().clone());
+            // volume-weighted rollups need the volume of each bar
+            let rollup = catalog.get(sm.metric()).map(|info| info.rollup());
+            if query.resample().is_some() && rollup == Some(Rollup::VolumeWeighted) {
+                target.add_metric(Metric::VOLUME);
+            }
         }
 
+        let step = Duration::from(query.step());
+        let warmup = query.lookback();
+        let range = DateRange::resolve(query.range(), now);
+
         QueryPlan {
             targets: Vec::from_iter(targets.values().cloned()),
-            range: Duration::from(query.for_clause()),
-            step: Duration::from(query.step()),
+            range: range.extended_back(step.saturating_mul(warmup.try_into().unwrap_or(u32::MAX))),
+            step: query.resample().map(Duration::from).unwrap_or(step),
+            warmup,
+            rows_count: range.steps(step),
+            resample: query.resample_factor(),
         }
     }
 }
@@ -60,6 +136,17 @@ impl From<&Query> for QueryPlan {
 fn collect_symbols<'a>(expr: &'a Expr, acc: &mut Vec<&'a SymbolMetric>) {
     match expr {
         Expr::Data(symbol) => acc.push(symbol),
+        Expr::Neg(expr) | Expr::Not(expr) => collect_symbols(expr, acc),
+        Expr::Call(_func, args) => args.iter().for_each(|arg| collect_symbols(arg, acc)),
+        Expr::Case(branches, default) => {
+            for (cond, value) in branches {
+                collect_symbols(cond, acc);
+                collect_symbols(value, acc);
+            }
+            if let Some(default) = default {
+                collect_symbols(default, acc);
+            }
+        }
         Expr::Binary(left, _op, right) => {
             collect_symbols(left, acc);
             collect_symbols(right, acc);
@@ -67,3 +154,122 @@ fn collect_symbols<'a>(expr: &'a Expr, acc: &mut Vec<&'a SymbolMetric>) {
         _ => {}
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use query_parser::{MetricInfo, Universes, parse_query};
+
+    #[test]
+    fn test_targets_by_exchange() {
+        let query = parse_query(
+            r#"GET XNAS:AAPL.close - XNYS:AAPL.close, AAPL.open, XNAS:AAPL.max, "BRK.B".min
+               FOR LAST 1 day STEP 1 hour"#,
+        )
+        .unwrap();
+        let plan = QueryPlan::for_query(&query, MetricCatalog::well_known(), Utc::now());
+
+        let mut targets: Vec<(String, Option<&str>, usize)> = plan
+            .targets()
+            .map(|t| (t.qualified_symbol(), t.exchange(), t.metrics().count()))
+            .collect();
+        targets.sort();
+        assert_eq!(
+            vec![
+                ("AAPL".to_string(), None, 1),
+                ("BRK.B".to_string(), None, 1),
+                ("XNAS:AAPL".to_string(), Some("XNAS"), 2),
+                ("XNYS:AAPL".to_string(), Some("XNYS"), 1),
+            ],
+            targets
+        );
+    }
+
+    #[test]
+    fn test_resample_fetches_volume() {
+        let query = parse_query(
+            r"GET AAPL.vwap, MSFT.close FOR LAST 1 day STEP 1 hour RESAMPLE 15 minutes",
+        )
+        .unwrap();
+        let catalog = MetricCatalog::default().with(
+            Metric::new("vwap"),
+            MetricInfo::new("currency", Rollup::VolumeWeighted),
+        );
+        let plan = QueryPlan::for_query(&query, &catalog, Utc::now());
+
+        let mut targets: Vec<(String, Vec<String>)> = plan
+            .targets()
+            .map(|t| {
+                let mut metrics: Vec<String> = t.metrics().map(|m| m.to_string()).collect();
+                metrics.sort();
+                (t.symbol().to_string(), metrics)
+            })
+            .collect();
+        targets.sort();
+        assert_eq!(
+            vec![
+                (
+                    "AAPL".to_string(),
+                    vec!["volume".to_string(), "vwap".to_string()]
+                ),
+                ("MSFT".to_string(), vec!["close".to_string()]),
+            ],
+            targets
+        );
+    }
+
+    #[test]
+    fn test_symbol_sets_fetched_once() {
+        let query = parse_query(
+            r"GET {AAPL, MSFT}.max - {AAPL, MSFT}.min AS range, {AAPL, MSFT}.close, AAPL.max
+              FOR LAST 1 day STEP 1 hour",
+        )
+        .unwrap();
+        let query = Universes::new().expand(&query).unwrap();
+        assert_eq!(5, query.columns().len());
+        let plan = QueryPlan::for_query(&query, MetricCatalog::well_known(), Utc::now());
+
+        let mut targets: Vec<(&str, usize)> = plan
+            .targets()
+            .map(|t| (t.symbol(), t.metrics().count()))
+            .collect();
+        targets.sort();
+        assert_eq!(vec![("AAPL", 3), ("MSFT", 3)], targets);
+    }
+
+    #[test]
+    fn test_range_resolved_once() {
+        let query = parse_query(r"GET SMA(AAPL.close, 3) FOR LAST 1 month STEP 1 day").unwrap();
+        let now: DateTime<Utc> = "2025-03-31T00:00:00Z".parse().unwrap();
+        let plan = QueryPlan::for_query(&query, MetricCatalog::well_known(), now);
+
+        assert_eq!(31, plan.rows_count());
+        assert_eq!(2, plan.warmup());
+        let from: DateTime<Utc> = plan.range().from().into();
+        let to: DateTime<Utc> = plan.range().to().into();
+        assert_eq!(
+            "2025-02-26T00:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+            from
+        );
+        assert_eq!(now, to);
+    }
+
+    #[test]
+    fn test_row_times() {
+        let query = parse_query(
+            "GET SMA(AAPL.volume, 2) FROM '2025-06-02T00:00:00Z' TO '2025-06-02T03:30:00Z' \
+             STEP 1 hour RESAMPLE 15 minutes",
+        )
+        .unwrap();
+        let plan = QueryPlan::for_query(&query, MetricCatalog::well_known(), Utc::now());
+
+        assert_eq!(Duration::from_secs(3600), plan.row_step());
+        let hours: Vec<String> = plan
+            .row_times()
+            .iter()
+            .map(|time| time.format("%H:%M").to_string())
+            .collect();
+        // one warm-up row, the partial step at 03:00 is not a row
+        assert_eq!(vec!["23:00", "00:00", "01:00", "02:00"], hours);
+    }
+}
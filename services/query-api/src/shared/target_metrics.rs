@@ -3,9 +3,11 @@ use std::collections::HashSet;
 use query_parser::Metric;
 
 
+/// Metrics of a symbol, fetched in a single request.
 #[derive(Debug, Clone)]
 pub struct TargetMetrics {
     symbol: String,
+    exchange: Option<String>,
     metrics: HashSet<Metric>,
 }
 
@@ -13,14 +15,32 @@ impl TargetMetrics {
     pub fn new(symbol: &str) -> Self {
         Self {
             symbol: symbol.to_string(),
+            exchange: None,
             metrics: HashSet::new()
         }
     }
 
+    pub fn with_exchange(mut self, exchange: Option<&str>) -> Self {
+        self.exchange = exchange.map(str::to_string);
+        self
+    }
+
     pub fn symbol(&self) -> &str {
         &self.symbol
     }
 
+    pub fn exchange(&self) -> Option<&str> {
+        self.exchange.as_deref()
+    }
+
+    /// The symbol with its exchange, if any (`XNAS:AAPL`); the key of its data.
+    pub fn qualified_symbol(&self) -> String {
+        match &self.exchange {
+            Some(exchange) => format!("{}:{}", exchange, self.symbol),
+            None => self.symbol.clone(),
+        }
+    }
+
     pub fn metrics(&self) -> impl Iterator<Item = &Metric> {
         self.metrics.iter()
     }
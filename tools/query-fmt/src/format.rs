@@ -0,0 +1,162 @@
+use query_parser::Query;
+
+/// Maximum length of the GET line; longer column lists are written one column per line.
+const MAX_WIDTH: usize = 100;
+const INDENT: &str = "    ";
+
+/// Formats the source of a query: the comments leading the source, then the query
+/// in canonical form with each clause on its own line. Comments elsewhere in the
+/// source would be lost, so such a source is not formatted.
+pub fn format_source(src: &str, query: &Query) -> Result<String, anyhow::Error> {
+    let (header, body) = split_header(src);
+    if has_comment(body) {
+        anyhow::bail!("comments are only kept before the query; move them above GET to format");
+    }
+
+    let mut formatted = String::new();
+    for line in header.trim_end().lines() {
+        formatted.push_str(line.trim_end());
+        formatted.push('\n');
+    }
+    formatted.push_str(&format_query(query));
+    Ok(formatted)
+}
+
+/// Writes the query one clause per line:
+///
+/// ```text
+/// GET AAPL.close AS close, SMA(AAPL.close, 20) AS sma
+/// FOR LAST 30 days
+/// STEP 1 day
+/// WHERE AAPL.volume > 1000
+/// ```
+pub fn format_query(query: &Query) -> String {
+    let columns: Vec<String> = query.columns().iter().map(|c| c.to_string()).collect();
+    let get = format!("GET {}", columns.join(", "));
+    let mut lines = match get.len() <= MAX_WIDTH {
+        true => vec![get],
+        false => vec![format!(
+            "GET\n{}{}",
+            INDENT,
+            columns.join(&format!(",\n{}", INDENT))
+        )],
+    };
+
+    lines.push(query.range().to_string());
+    lines.push(format!("STEP {}", query.step()));
+    if let Some(resample) = query.resample() {
+        lines.push(format!("RESAMPLE {}", resample));
+    }
+    if let Some(filter) = query.filter() {
+        lines.push(format!("WHERE {}", filter));
+    }
+    if let Some(order_by) = query.order_by() {
+        lines.push(format!("ORDER BY {}", order_by));
+    }
+    if let Some(limit) = query.limit() {
+        lines.push(match query.offset() {
+            0 => format!("LIMIT {}", limit),
+            offset => format!("LIMIT {} OFFSET {}", limit, offset),
+        });
+    }
+    if query.summary() {
+        lines.push("SUMMARY".to_string());
+    }
+
+    lines.join("\n") + "\n"
+}
+
+/// Splits the source into the leading comments (and whitespace) and the rest.
+fn split_header(src: &str) -> (&str, &str) {
+    let mut pos = 0;
+    loop {
+        let rest = &src[pos..];
+        let trimmed = rest.trim_start();
+        let skip = if trimmed.starts_with("--") {
+            trimmed.find('\n').map_or(trimmed.len(), |len| len + 1)
+        } else if let Some(comment) = trimmed.strip_prefix("/*") {
+            match comment.find("*/") {
+                Some(len) => len + 4,
+                None => break,
+            }
+        } else {
+            break;
+        };
+        pos += rest.len() - trimmed.len() + skip;
+    }
+    src.split_at(pos)
+}
+
+/// Whether there is a comment in the source.
+fn has_comment(src: &str) -> bool {
+    unquoted(src).any(|(pos, _)| src[pos..].starts_with("--") || src[pos..].starts_with("/*"))
+}
+
+/// Characters of the source outside quoted timestamps and symbols, with their positions.
+fn unquoted(src: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut quote = None;
+    src.char_indices().filter(move |&(_, c)| match quote {
+        Some(open) => {
+            if c == open {
+                quote = None;
+            }
+            false
+        }
+        None if c == '\'' || c == '"' => {
+            quote = Some(c);
+            false
+        }
+        None => true,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use query_parser::parse_query;
+
+    #[test]
+    fn test_format_source() {
+        let src = "-- daily closes  \n\n/* of Apple */ get AAPL.close as c for last 30 days step 1 day\n\
+                   where AAPL.volume>1000 limit 5 offset 0 summary";
+        let query = parse_query(src).unwrap();
+        let formatted = format_source(src, &query).unwrap();
+        assert_eq!(
+            "-- daily closes\n\n/* of Apple */\nGET AAPL.close AS c\nFOR LAST 30 days\nSTEP 1 day\n\
+             WHERE AAPL.volume > 1000\nLIMIT 5\nSUMMARY\n",
+            formatted
+        );
+        assert_eq!(query, parse_query(&formatted).unwrap());
+        assert_eq!(formatted, format_source(&formatted, &query).unwrap());
+
+        let src = "GET AAPL.close -- price\nFOR LAST 30 days STEP 1 day";
+        assert!(format_source(src, &parse_query(src).unwrap()).is_err());
+
+        let src = "GET \"A--B\".close FOR LAST 30 days STEP 1 day";
+        assert_eq!(
+            "GET \"A--B\".close\nFOR LAST 30 days\nSTEP 1 day\n",
+            format_source(src, &parse_query(src).unwrap()).unwrap()
+        );
+
+        let src = "get {AAPL,\"BRK--B\"}.close - { AAPL, \"BRK--B\" }.open as d for last 30 days step 1 day";
+        assert_eq!(
+            "GET {AAPL, \"BRK--B\"}.close - {AAPL, \"BRK--B\"}.open AS d\nFOR LAST 30 days\nSTEP 1 day\n",
+            format_source(src, &parse_query(src).unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_format_long_column_list() {
+        let src = "GET SMA(AAPL.close, 20) AS aapl_sma, SMA(MSFT.close, 20) AS msft_sma, \
+                   SMA(GOOGL.close, 20) AS googl_sma FROM '2025-06-01T00:00:00Z' TO '2025-06-05T00:00:00Z' \
+                   STEP 1 hour RESAMPLE 15 minutes ORDER BY aapl_sma DESC";
+        let query = parse_query(src).unwrap();
+        assert_eq!(
+            "GET\n    SMA(AAPL.close, 20) AS aapl_sma,\n    SMA(MSFT.close, 20) AS msft_sma,\n    \
+             SMA(GOOGL.close, 20) AS googl_sma\nFROM '2025-06-01T00:00:00Z' TO '2025-06-05T00:00:00Z'\n\
+             STEP 1 hour\nRESAMPLE 15 minutes\nORDER BY aapl_sma DESC\n",
+            format_query(&query)
+        );
+        assert_eq!(query, parse_query(&format_query(&query)).unwrap());
+    }
+}
@@ -0,0 +1,142 @@
+mod format;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use query_parser::{
+    Metric, MetricCatalog, MetricInfo, Rollup, Severity, parse_query, parse_query_recovering,
+    validate,
+};
+
+const USAGE: &str = "Usage: query-fmt [--check] <file.ql | directory>...
+
+Formats query files in place (directories are searched for .ql files)
+and reports the problems found by parsing and validating the queries.
+
+Options:
+  --check            Don't write the files, fail if any of them is not formatted
+  --metrics <names>  Comma-separated metrics to accept besides the well-known ones";
+
+fn main() -> ExitCode {
+    let mut check = false;
+    let mut catalog = MetricCatalog::default();
+    let mut paths = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--metrics" => {
+                let Some(names) = args.next() else {
+                    eprintln!("Missing metric names\n\n{}", USAGE);
+                    return ExitCode::from(2);
+                };
+                // only the names matter for validating
+                catalog.extend(names.split(',').map(|name| {
+                    let info = MetricInfo::new("", Rollup::Last);
+                    (Metric::new(name.trim().to_string()), info)
+                }));
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            option if option.starts_with('-') => {
+                eprintln!("Unknown option: {}\n\n{}", option, USAGE);
+                return ExitCode::from(2);
+            }
+            path => paths.push(PathBuf::from(path)),
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    }
+
+    let mut failed = false;
+    for path in paths {
+        let files = match query_files(&path) {
+            Ok(files) => files,
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                failed = true;
+                continue;
+            }
+        };
+        for file in files {
+            match process_file(&file, check, &catalog) {
+                Ok(ok) => failed |= !ok,
+                Err(err) => {
+                    eprintln!("{}: {}", file.display(), err);
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    match failed {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS,
+    }
+}
+
+/// Formats (or checks) a single file and prints the issues of its query.
+/// Returns false if the file has errors or, when checking, is not formatted.
+fn process_file(file: &Path, check: bool, catalog: &MetricCatalog) -> Result<bool, anyhow::Error> {
+    let src = fs::read_to_string(file)?;
+    let query = match parse_query(&src) {
+        Ok(query) => query,
+        Err(_) => {
+            // all the problems of the query, rather than the first one
+            for err in parse_query_recovering(&src, catalog).diagnostics() {
+                eprintln!("{}: error: {}", file.display(), err);
+                if let Some(snippet) = err.snippet() {
+                    eprintln!("{}", snippet);
+                }
+            }
+            return Ok(false);
+        }
+    };
+
+    let mut valid = true;
+    for issue in validate(&query, catalog) {
+        let severity = match issue.severity() {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        eprintln!("{}: {}: {}", file.display(), severity, issue);
+        valid &= !issue.is_error();
+    }
+
+    let formatted = format::format_source(&src, &query)?;
+    if formatted == src {
+        return Ok(valid);
+    }
+    if check {
+        println!("{}: not formatted", file.display());
+        return Ok(false);
+    }
+    fs::write(file, formatted)?;
+    println!("{}: formatted", file.display());
+    Ok(valid)
+}
+
+/// The file itself, or the `.ql` files in a directory and its subdirectories (sorted).
+fn query_files(path: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(query_files(&path)?);
+        } else if path.extension().is_some_and(|ext| ext == "ql") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}